@@ -1,13 +1,10 @@
-use std::env;   
-use std::path::Path;
-use std::process::Command;
-
-// Bring in a dependency on an externally maintained `gcc` package which manages
+// Bring in a dependency on an externally maintained `cc` package which manages
 // invoking the C compiler.
-extern crate gcc;
+extern crate cc;
 
 fn main() {
-    gcc::compile_library("libtweetnacl.a", &["lib/tweetnacl/tweetnacl.c"]);
+    // Vendored as is, its warnings are not ours to fix
+    cc::Build::new().file("lib/tweetnacl/tweetnacl.c").warnings(false).compile("tweetnacl");
 
 /*
     let out_dir = env::var("OUT_DIR").unwrap();
@@ -0,0 +1,27 @@
+use std::io;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use agent;
+use agent::protocol::{Request, Response, read_message, write_message};
+use agent::server::CONNECTION_TIMEOUT;
+
+/// Send the request to the running agent, None if no agent is running.
+pub fn request(request: &Request) -> Option<io::Result<Response>> {
+    let path = match agent::socket_path() {
+        Ok(path) => path,
+        Err(_) => return None
+    };
+    let mut stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(_) => return None
+    };
+    Some(exchange(&mut stream, request))
+}
+
+fn exchange(stream: &mut UnixStream, request: &Request) -> io::Result<Response> {
+    // The password is going to come from there, it has to be us
+    agent::check_peer(stream)?;
+    stream.set_read_timeout(Some(Duration::from_secs(CONNECTION_TIMEOUT)))?;
+    write_message(stream, request)?;
+    read_message(stream)
+}
@@ -0,0 +1,78 @@
+//!
+//! Background agent holding unlocked vaults, so that one-shot commands don't
+//! have to ask for the master password every time.
+//!
+//! The agent listens on a Unix domain socket in a directory only the user can
+//! enter, and additionally checks the uid of every peer (SO_PEERCRED). It only
+//! reads the vaults, so it never takes their locks and other rpass processes
+//! can keep editing them; changed files are decrypted again with the key the
+//! agent already has. For the same reason uses of entries through the agent
+//! aren't recorded in `last_used`.
+//!
+
+pub mod protocol;
+pub mod server;
+pub mod client;
+
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::io::{self, Error, ErrorKind};
+use std::mem;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use libc;
+
+/// Environment variable with the socket path, overriding the default location.
+pub const SOCKET_ENV: &str = "RPASS_AGENT_SOCKET";
+
+const SOCKET_NAME: &str = "agent.sock";
+
+/// Where the agent listens: `$RPASS_AGENT_SOCKET`, or `rpass/agent.sock` in
+/// `$XDG_RUNTIME_DIR`, or `/tmp/rpass-<uid>/agent.sock`. The directory is
+/// created private if it doesn't exist.
+pub fn socket_path() -> io::Result<PathBuf> {
+    if let Some(path) = env::var_os(SOCKET_ENV) {
+        // The agent leaves the current directory
+        return Ok(env::current_dir()?.join(path));
+    }
+    let uid = unsafe { libc::getuid() };
+    let dir = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => PathBuf::from(runtime_dir).join("rpass"),
+        None => env::temp_dir().join(format!("rpass-{}", uid))
+    };
+    if !dir.exists() {
+        DirBuilder::new().mode(0o700).recursive(true).create(&dir)?;
+    }
+
+    // In a shared place like /tmp somebody else could have prepared the directory
+    let metadata = fs::metadata(&dir)?;
+    if metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(Error::new(ErrorKind::PermissionDenied,
+            format!("{} has to be a directory accessible only by you", dir.display())));
+    }
+    Ok(dir.join(SOCKET_NAME))
+}
+
+/// Uid of the process on the other side of the socket.
+pub fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut credentials: libc::ucred = unsafe { mem::zeroed() };
+    let mut length = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED,
+                         &mut credentials as *mut libc::ucred as *mut libc::c_void, &mut length)
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(credentials.uid)
+}
+
+/// Refuse peers running as another user, root included.
+pub fn check_peer(stream: &UnixStream) -> io::Result<()> {
+    if peer_uid(stream)? != unsafe { libc::getuid() } {
+        return Err(Error::new(ErrorKind::PermissionDenied, "peer runs as another user"));
+    }
+    Ok(())
+}
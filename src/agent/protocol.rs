@@ -0,0 +1,139 @@
+//!
+//! Messages between the agent and its clients.
+//!
+//! Every message is a frame: the length of the body as a 4 byte big-endian
+//! number followed by the body, a JSON object. A connection carries exactly one
+//! request and one response.
+//!
+
+use std::io::{self, Read, Write, Error, ErrorKind};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+/// Largest frame accepted, nothing the agent sends comes close.
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Request {
+    /// `list`, `show`, `copy` or `lock`.
+    pub command: String,
+    /// Canonical path of the vault, empty for `lock`.
+    #[serde(default)]
+    pub vault: String,
+    /// Entry for `show` and `copy`.
+    #[serde(default)]
+    pub entry: String,
+    /// Group listed by `list`, all entries if empty.
+    #[serde(default)]
+    pub group: String,
+    /// Single field of the entry for `show`.
+    #[serde(default)]
+    pub field: Option<String>,
+    /// Send secrets of `show` in plaintext instead of the mask.
+    #[serde(default)]
+    pub reveal: bool
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Response {
+    /// Why the request failed, the other fields are empty then.
+    #[serde(default)]
+    pub error: Option<String>,
+    /// The entry of the request doesn't exist.
+    #[serde(default)]
+    pub entry_not_found: bool,
+    /// The agent doesn't hold the vault, the client has to open the file itself.
+    #[serde(default)]
+    pub unknown_vault: bool,
+    /// Summary of the listed entries, one row per entry.
+    #[serde(default)]
+    pub rows: Vec<Vec<(String, String)>>,
+    /// Details of the shown entry, the single requested field, or the password to copy.
+    #[serde(default)]
+    pub fields: Vec<(String, String)>
+}
+
+impl Response {
+    pub fn error(message: String) -> Response {
+        Response { error: Some(message), ..Response::default() }
+    }
+}
+
+/// Send one message as a frame.
+pub fn write_message<T: Serialize, W: Write>(out: &mut W, message: &T) -> io::Result<()> {
+    let mut body = serde_json::to_vec(message)
+        .map_err(|why| Error::new(ErrorKind::InvalidData, format!("{}", why)))?;
+    let res = write_frame(out, &body);
+    // The body can hold passwords
+    wipe(&mut body);
+    res
+}
+
+/// Receive one message sent by `write_message`.
+pub fn read_message<T: DeserializeOwned, R: Read>(input: &mut R) -> io::Result<T> {
+    let mut body = read_frame(input)?;
+    let res = serde_json::from_slice(&body).map_err(|why| Error::new(ErrorKind::InvalidData, format!("{}", why)));
+    wipe(&mut body);
+    res
+}
+
+fn write_frame<W: Write>(out: &mut W, body: &[u8]) -> io::Result<()> {
+    if body.len() > MAX_FRAME_SIZE {
+        return Err(Error::new(ErrorKind::InvalidInput, "message is too large"));
+    }
+    let length = body.len() as u32;
+    let header = [(length >> 24) as u8, (length >> 16) as u8, (length >> 8) as u8, length as u8];
+    out.write_all(&header)?;
+    out.write_all(body)?;
+    out.flush()
+}
+
+fn read_frame<R: Read>(input: &mut R) -> io::Result<Vec<u8>> {
+    let mut header = [0u8; 4];
+    input.read_exact(&mut header)?;
+    let length = header.iter().fold(0usize, |length, &byte| (length << 8) | byte as usize);
+    if length > MAX_FRAME_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "message is too large"));
+    }
+    let mut body = vec![0u8; length];
+    input.read_exact(&mut body)?;
+    Ok(body)
+}
+
+fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        *byte = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::{Request, Response, read_message, write_message, MAX_FRAME_SIZE};
+
+    #[test]
+    fn test_round_trip() {
+        let request = Request { command: "show".to_string(), vault: "/tmp/vault.db".to_string(),
+                                entry: "github".to_string(), reveal: true, ..Request::default() };
+        let mut buff = Cursor::new(Vec::new());
+        write_message(&mut buff, &request).unwrap();
+        write_message(&mut buff, &Response::error("no such vault".to_string())).unwrap();
+
+        buff.set_position(0);
+        assert_eq!(read_message::<Request, _>(&mut buff).unwrap(), request);
+        let response: Response = read_message(&mut buff).unwrap();
+        assert_eq!(response.error, Some("no such vault".to_string()));
+    }
+
+    #[test]
+    fn test_bad_frames() {
+        // Truncated body
+        let mut input = Cursor::new(vec![0, 0, 0, 10, b'{']);
+        assert!(read_message::<Request, _>(&mut input).is_err());
+
+        let length = MAX_FRAME_SIZE as u32 + 1;
+        let mut input = Cursor::new(vec![(length >> 24) as u8, (length >> 16) as u8, (length >> 8) as u8, length as u8]);
+        assert!(read_message::<Request, _>(&mut input).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use libc;
+use db::{Database, DatabaseInFile};
+use db::group;
+use readline;
+use agent;
+use agent::protocol::{Request, Response, read_message, write_message};
+
+/// Seconds a client gets to send its request.
+pub const CONNECTION_TIMEOUT: u64 = 10;
+
+pub struct Agent {
+    /// Opened vaults by their canonical path.
+    vaults: BTreeMap<String, DatabaseInFile>,
+    /// Stop after this long without a request.
+    idle_timeout: Option<Duration>,
+    /// Stop after this long in any case.
+    lifetime: Option<Duration>
+}
+
+/// Listen on the socket, readable and writable only by the user. A socket left
+/// behind by a dead agent is replaced, a live agent is an error.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(Error::new(ErrorKind::AddrInUse, "another agent is already running"));
+        }
+        fs::remove_file(path)?;
+    }
+    // Created with the right mode, a chmod afterwards would leave a window open
+    let old_mask = unsafe { libc::umask(0o177) };
+    let res = UnixListener::bind(path);
+    unsafe { libc::umask(old_mask) };
+    res
+}
+
+impl Agent {
+    pub fn new(vaults: BTreeMap<String, DatabaseInFile>, idle_timeout: Option<Duration>,
+               lifetime: Option<Duration>) -> Agent {
+        Agent { vaults, idle_timeout, lifetime }
+    }
+
+    /// Answer requests until a timeout or `lock`, then wipe the vaults and remove the socket.
+    pub fn serve(mut self, listener: UnixListener, path: &Path) {
+        let started = Instant::now();
+        let mut last_request = started;
+        // Ctrl-C stops an agent in the foreground
+        while !readline::interrupted() {
+            let wait = match (remaining(started, self.lifetime), remaining(last_request, self.idle_timeout)) {
+                (Some(a), Some(b)) => Some(cmp::min(a, b)),
+                (a, b) => a.or(b)
+            };
+            if wait == Some(Duration::from_secs(0)) {
+                break;
+            }
+            match wait_for_client(&listener, wait) {
+                Ok(true) => (),
+                Ok(false) => continue,
+                // Nobody to tell in the background
+                Err(_) => break
+            }
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(_) => continue
+            };
+            if let Ok(stop) = self.handle_connection(stream) {
+                last_request = Instant::now();
+                if stop {
+                    break;
+                }
+            }
+        }
+
+        let _ = fs::remove_file(path);
+        for (_, file_db) in self.vaults {
+            file_db.close();
+        }
+    }
+
+    // True if the agent should stop
+    fn handle_connection(&mut self, mut stream: UnixStream) -> io::Result<bool> {
+        agent::check_peer(&stream)?;
+        stream.set_read_timeout(Some(Duration::from_secs(CONNECTION_TIMEOUT)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(CONNECTION_TIMEOUT)))?;
+        let request: Request = read_message(&mut stream)?;
+        if request.command == "lock" {
+            write_message(&mut stream, &Response::default())?;
+            return Ok(true);
+        }
+        let response = self.handle(&request);
+        write_message(&mut stream, &response)?;
+        Ok(false)
+    }
+
+    fn handle(&mut self, request: &Request) -> Response {
+        let readable = match self.vaults.get_mut(&request.vault) {
+            Some(file_db) => file_db.refresh().is_ok(),
+            None => return Response { unknown_vault: true, ..Response::default() }
+        };
+        if !readable {
+            // Most likely a new master password, the client asks for it and opens the file itself
+            if let Some(file_db) = self.vaults.remove(&request.vault) {
+                file_db.close();
+            }
+            return Response { unknown_vault: true, ..Response::default() };
+        }
+
+        let db = &mut self.vaults.get_mut(&request.vault).unwrap().db;
+        match &request.command[..] {
+            "list" => list(db, &request.group),
+            "show" => show(db, request),
+            "copy" => copy(db, &request.entry),
+            other => Response::error(format!("Unknown request '{}'.", other))
+        }
+    }
+}
+
+fn list(db: &Database, path: &str) -> Response {
+    if !db.group_exists(path) {
+        return Response::error(format!("There is no group '{}'.", path));
+    }
+    let rows = db.entries.iter()
+        .filter(|entry| group::contains(path, &entry.group))
+        .map(|entry| entry.summary_fields().into_iter().map(|(name, value)| (name.to_string(), value)).collect())
+        .collect();
+    Response { rows, ..Response::default() }
+}
+
+fn show(db: &mut Database, request: &Request) -> Response {
+    let entry_id = match choose(db, &request.entry) {
+        Ok(entry_id) => entry_id,
+        Err(response) => return response
+    };
+    let entry = db.get_mut(&entry_id).unwrap();
+    let fields = match request.field {
+        Some(ref name) => match entry.field_value(name) {
+            Some(value) => vec![(name.clone(), value)],
+            None => return Response::error(format!("Entry has no field '{}'.", name))
+        },
+        None => entry.detail_fields(request.reveal)
+    };
+    Response { fields, ..Response::default() }
+}
+
+fn copy(db: &mut Database, query: &str) -> Response {
+    let entry_id = match choose(db, query) {
+        Ok(entry_id) => entry_id,
+        Err(response) => return response
+    };
+    let password = db.get_mut(&entry_id).unwrap().field_value("password").unwrap();
+    Response { fields: vec![("password".to_string(), password)], ..Response::default() }
+}
+
+// There is nobody to ask which entry was meant, so only exact matches count
+// and more of them are an error
+fn choose(db: &Database, query: &str) -> Result<String, Response> {
+    let matches = db.find(query);
+    match matches.len() {
+        0 => Err(Response { entry_not_found: true, ..Response::default() }),
+        1 => Ok(matches[0].id.clone()),
+        count => {
+            let names: Vec<String> = matches.iter()
+                .map(|entry| format!("{} ({})", entry.path(), entry.short_id()))
+                .collect();
+            Err(Response::error(format!("There are {} entries matching '{}': {}. Use the id to choose one.",
+                                        count, query, names.join(", "))))
+        }
+    }
+}
+
+fn remaining(since: Instant, timeout: Option<Duration>) -> Option<Duration> {
+    timeout.map(|timeout| {
+        let elapsed = since.elapsed();
+        if elapsed >= timeout { Duration::from_secs(0) } else { timeout - elapsed }
+    })
+}
+
+// False if no client connected within the time
+fn wait_for_client(listener: &UnixListener, wait: Option<Duration>) -> io::Result<bool> {
+    let millis = match wait {
+        Some(wait) => {
+            let millis = wait.as_secs() * 1000 + wait.subsec_millis() as u64;
+            // At least a millisecond, zero would return at once and spin
+            cmp::max(1, cmp::min(millis, libc::c_int::MAX as u64)) as libc::c_int
+        },
+        None => -1
+    };
+    let mut fds = [libc::pollfd { fd: listener.as_raw_fd(), events: libc::POLLIN, revents: 0 }];
+    match unsafe { libc::poll(fds.as_mut_ptr(), 1, millis) } {
+        -1 => {
+            let err = io::Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted { Ok(false) } else { Err(err) }
+        },
+        0 => Ok(false),
+        _ => Ok(true)
+    }
+}
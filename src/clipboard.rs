@@ -0,0 +1,255 @@
+//!
+//! Clipboard backends used by the `copy` command.
+//!
+//! There is no portable clipboard API, so the X11 and Wayland backends shell
+//! out to `xclip` and `wl-copy`/`wl-paste`. The secret is passed through the
+//! child's stdin, never on the command line where other users could see it.
+//!
+
+use std::env;
+use std::io::{self, Read, Write, Error};
+use std::process::{Command, Stdio};
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+
+/// Default number of seconds after which a copied password is cleared.
+pub const DEFAULT_CLEAR_TIMEOUT: u64 = 30;
+
+const DIGEST_BYTES: usize = 32;
+
+/// Clears waiting in their threads, `clear_pending` runs them before the process ends.
+static PENDING: Mutex<Vec<PendingClear>> = Mutex::new(Vec::new());
+
+struct PendingClear {
+    /// Wakes the thread up to clear at once.
+    wake: Sender<()>,
+    thread: JoinHandle<()>
+}
+
+pub trait Clipboard {
+    /// Replace the clipboard content with the given text.
+    fn set(&mut self, text: &str) -> io::Result<()>;
+    /// Read the current clipboard content.
+    fn get(&mut self) -> io::Result<String>;
+    /// Empty the clipboard.
+    fn clear(&mut self) -> io::Result<()>;
+}
+
+/// X11 clipboard, requires `xclip` to be installed.
+pub struct X11Clipboard;
+
+impl Clipboard for X11Clipboard {
+    fn set(&mut self, text: &str) -> io::Result<()> {
+        write_to_command("xclip", &["-selection", "clipboard", "-in"], text)
+    }
+
+    fn get(&mut self) -> io::Result<String> {
+        read_from_command("xclip", &["-selection", "clipboard", "-out"])
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        write_to_command("xclip", &["-selection", "clipboard", "-in"], "")
+    }
+}
+
+/// Wayland clipboard, requires `wl-clipboard` (wl-copy, wl-paste) to be installed.
+pub struct WaylandClipboard;
+
+impl Clipboard for WaylandClipboard {
+    fn set(&mut self, text: &str) -> io::Result<()> {
+        write_to_command("wl-copy", &[], text)
+    }
+
+    fn get(&mut self) -> io::Result<String> {
+        read_from_command("wl-paste", &["--no-newline"])
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        write_to_command("wl-copy", &["--clear"], "")
+    }
+}
+
+/// In-memory clipboard which records every write, used in tests.
+#[cfg(test)]
+pub struct MemoryClipboard {
+    pub content: String,
+    pub writes: Vec<String>
+}
+
+#[cfg(test)]
+impl MemoryClipboard {
+    pub fn new() -> MemoryClipboard {
+        MemoryClipboard { content: String::new(), writes: Vec::new() }
+    }
+}
+
+#[cfg(test)]
+impl Clipboard for MemoryClipboard {
+    fn set(&mut self, text: &str) -> io::Result<()> {
+        self.content = text.to_string();
+        self.writes.push(text.to_string());
+        Ok(())
+    }
+
+    fn get(&mut self) -> io::Result<String> {
+        Ok(self.content.clone())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.content.clear();
+        Ok(())
+    }
+}
+
+/// Pick a clipboard backend based on the running display server.
+pub fn detect() -> Option<Box<dyn Clipboard + Send>> {
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        Some(Box::new(WaylandClipboard))
+    } else if env::var_os("DISPLAY").is_some() {
+        Some(Box::new(X11Clipboard))
+    } else {
+        None
+    }
+}
+
+/// Hash of the copied secret, so that we can recognize it later
+/// without keeping the plaintext around.
+pub fn digest(text: &str) -> [u8; DIGEST_BYTES] {
+    digest_bytes(text.as_bytes())
+}
+
+fn digest_bytes(bytes: &[u8]) -> [u8; DIGEST_BYTES] {
+    let mut hasher = Sha256::new();
+    hasher.input(bytes);
+    let mut out = [0u8; DIGEST_BYTES];
+    hasher.result(&mut out);
+    out
+}
+
+/// Clear the clipboard, but only if it still holds the secret with the given digest.
+/// Returns true if the clipboard was cleared.
+pub fn clear_if_unchanged(clipboard: &mut dyn Clipboard, secret_digest: &[u8]) -> io::Result<bool> {
+    // Most likely the secret itself, it must not stay in memory
+    let mut current = clipboard.get()?.into_bytes();
+    let unchanged = fixed_time_eq(&digest_bytes(&current), secret_digest);
+    for byte in current.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    if unchanged {
+        clipboard.clear()?;
+    }
+    Ok(unchanged)
+}
+
+/// Clear the clipboard in a background thread after `timeout` seconds, or
+/// earlier by `clear_pending`.
+pub fn schedule_clear(mut clipboard: Box<dyn Clipboard + Send>, secret_digest: [u8; DIGEST_BYTES], timeout: u64) {
+    let (wake, woken) = mpsc::channel();
+    let thread = thread::spawn(move || {
+        let _ = woken.recv_timeout(Duration::from_secs(timeout));
+        // Nobody is listening in the background, errors can be only ignored
+        let _ = clear_if_unchanged(&mut *clipboard, &secret_digest);
+    });
+    let mut pending = PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    pending.retain(|clear| !clear.thread.is_finished());
+    pending.push(PendingClear { wake, thread });
+}
+
+/// Wait until the scheduled clears have run, or run them at once when `stop` says so.
+pub fn wait_pending(stop: fn() -> bool) {
+    for clear in take_pending() {
+        while !clear.thread.is_finished() && !stop() {
+            thread::sleep(Duration::from_millis(100));
+        }
+        let _ = clear.wake.send(());
+        let _ = clear.thread.join();
+    }
+}
+
+/// Run the scheduled clears now, before the process ends or the session is locked.
+pub fn clear_pending() {
+    for clear in take_pending() {
+        let _ = clear.wake.send(());
+        let _ = clear.thread.join();
+    }
+}
+
+fn take_pending() -> Vec<PendingClear> {
+    let mut pending = PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    pending.drain(..).collect()
+}
+
+fn write_to_command(program: &str, args: &[&str], input: &str) -> io::Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    {
+        let stdin = child.stdin.as_mut().ok_or(Error::other("no stdin"))?;
+        stdin.write_all(input.as_bytes())?;
+    }
+    // Drop stdin so the child sees EOF
+    child.stdin = None;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(Error::other(format!("{} exited with {}", program, status)));
+    }
+    Ok(())
+}
+
+fn read_from_command(program: &str, args: &[&str]) -> io::Result<String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut output = String::new();
+    if let Some(ref mut stdout) = child.stdout {
+        stdout.read_to_string(&mut output)?;
+    }
+    child.wait()?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clipboard, MemoryClipboard, PENDING, clear_if_unchanged, clear_pending, digest, schedule_clear};
+
+    #[test]
+    fn test_clear_if_unchanged() {
+        let mut clipboard = MemoryClipboard::new();
+        clipboard.set("secret").unwrap();
+
+        assert!(clear_if_unchanged(&mut clipboard, &digest("secret")).unwrap());
+        assert_eq!(clipboard.content, "");
+    }
+
+    #[test]
+    fn test_keep_foreign_content() {
+        let mut clipboard = MemoryClipboard::new();
+        clipboard.set("secret").unwrap();
+        clipboard.set("something else").unwrap();
+
+        assert!(!clear_if_unchanged(&mut clipboard, &digest("secret")).unwrap());
+        assert_eq!(clipboard.content, "something else");
+        assert_eq!(clipboard.writes.len(), 2);
+    }
+    #[test]
+    fn test_clear_pending() {
+        let mut clipboard = MemoryClipboard::new();
+        clipboard.set("secret").unwrap();
+        // Would block for an hour if the thread wasn't woken up
+        schedule_clear(Box::new(clipboard), digest("secret"), 3600);
+        clear_pending();
+        assert!(PENDING.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::time::Duration;
+use libc;
+use agent::{self, client};
+use agent::protocol::{Request, Response};
+use agent::server::{self, Agent};
+use clipboard;
+use db::{DatabaseInFile, DbError};
+use db::group;
+use secstr::SecStr;
+use commands::copy;
+use commands::error::{CommandError, CommandResult};
+use commands::format::{self, OutputOptions};
+use commands::input::PasswordSource;
+use commands::open::MAX_PASS_ATTEMPTS;
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass agent <file>... [--idle <minutes>] [--lifetime <minutes>] [--foreground]
+       rpass agent lock";
+
+pub const DEFAULT_IDLE_MINUTES: u64 = 15;
+pub const DEFAULT_LIFETIME_MINUTES: u64 = 8 * 60;
+
+/// Unlock the vaults and serve them in the background, or stop the running agent.
+pub fn call(params: &[String], source: &PasswordSource) -> CommandResult<()> {
+    if params.len() == 1 && params[0] == "lock" {
+        return lock();
+    }
+
+    let mut files = Vec::new();
+    let mut idle = Some(DEFAULT_IDLE_MINUTES);
+    let mut lifetime = Some(DEFAULT_LIFETIME_MINUTES);
+    let mut foreground = false;
+    let mut iter = params.iter();
+    while let Some(param) = iter.next() {
+        match &param[..] {
+            "--idle" => idle = parse_minutes(iter.next())?,
+            "--lifetime" => lifetime = parse_minutes(iter.next())?,
+            "--foreground" => foreground = true,
+            _ => files.push(param)
+        }
+    }
+    if files.is_empty() {
+        return Err(CommandError::Usage(USAGE));
+    }
+
+    let path = agent::socket_path()?;
+    let listener = server::bind(&path)?;
+    let vaults = match open_vaults(&files, source) {
+        Ok(vaults) => vaults,
+        Err(why) => {
+            let _ = fs::remove_file(&path);
+            return Err(why);
+        }
+    };
+
+    if !foreground {
+        match unsafe { libc::fork() } {
+            -1 => return Err(CommandError::Io(io::Error::last_os_error())),
+            0 => detach(),
+            pid => {
+                println!("Agent started with pid {}, stop it with 'rpass agent lock'.", pid);
+                for (_, file_db) in vaults {
+                    file_db.close();
+                }
+                return Ok(());
+            }
+        }
+    } else {
+        println!("Agent is listening on {}.", path.display());
+    }
+
+    let to_duration = |minutes: Option<u64>| minutes.map(|minutes| Duration::from_secs(minutes * 60));
+    Agent::new(vaults, to_duration(idle), to_duration(lifetime)).serve(listener, &path);
+    Ok(())
+}
+
+// Don't get killed with the terminal session, and don't keep its descriptors
+// or directory busy: `x=$(rpass agent vault.db)` waits until stdout is closed
+fn detach() {
+    unsafe {
+        libc::setsid();
+        let null = libc::open(b"/dev/null\0".as_ptr() as *const libc::c_char, libc::O_RDWR);
+        if null >= 0 {
+            for fd in 0..3 {
+                libc::dup2(null, fd);
+            }
+            if null > 2 {
+                libc::close(null);
+            }
+        }
+        libc::chdir(b"/\0".as_ptr() as *const libc::c_char);
+    }
+}
+
+/// Run a read-only one-shot command through the agent, if it runs and holds the vault.
+/// None means the command has to open the file itself.
+pub fn run_through_agent(command: &str, file: &str, params: &[&str]) -> Option<CommandResult<()>> {
+    let vault = match fs::canonicalize(file) {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(_) => return None
+    };
+    // Invalid parameters are reported by the command itself
+    let (options, rest) = match OutputOptions::from_params(params) {
+        Ok(parsed) => parsed,
+        Err(_) => return None
+    };
+
+    let mut request = Request { command: command.to_string(), vault, ..Request::default() };
+    let mut timeout = 0;
+    match command {
+        "list" => match rest.len() {
+            0 => (),
+            1 if rest[0] != "--tree" => match group::normalize(rest[0]) {
+                Ok(path) => request.group = path,
+                Err(_) => return None
+            },
+            _ => return None
+        },
+        "show" => {
+            match rest.len() {
+                1 => (),
+                3 if rest[1] == "--field" => request.field = Some(rest[2].to_string()),
+                _ => return None
+            }
+            request.entry = rest[0].to_string();
+            request.reveal = options.reveal;
+        },
+        "copy" => {
+            timeout = match copy::parse_timeout(&rest) {
+                Ok(timeout) => timeout,
+                Err(_) => return None
+            };
+            request.entry = rest[0].to_string();
+        },
+        _ => return None
+    }
+
+    match client::request(&request) {
+        Some(Ok(response)) => if response.unknown_vault {
+            None
+        } else {
+            Some(output(&request, response, &options, timeout))
+        },
+        // No agent, or a broken one
+        _ => None
+    }
+}
+
+fn output(request: &Request, response: Response, options: &OutputOptions, timeout: u64) -> CommandResult<()> {
+    if response.entry_not_found {
+        return Err(CommandError::EntryNotFound(request.entry.clone()));
+    }
+    if let Some(message) = response.error {
+        return Err(CommandError::InvalidInput(message));
+    }
+    match &request.command[..] {
+        "list" => format::print_rows(&response.rows, options),
+        "show" => match request.field {
+            Some(_) => println!("{}", response.fields[0].1),
+            None => format::print_record(&response.fields, options)
+        },
+        _ => {
+            let mut backend = copy::detect_clipboard()?;
+            let mut password = SecStr::new(response.fields[0].1.clone());
+            password.unlock();
+            let res = backend.set(&password.string);
+            let digest = clipboard::digest(&password.string);
+            password.delete();
+            if let Err(why) = res {
+                return Err(CommandError::Clipboard(format!("{}", why)));
+            }
+            copy::clear_later(backend, digest, timeout, true);
+        }
+    }
+    Ok(())
+}
+
+fn lock() -> CommandResult<()> {
+    match client::request(&Request { command: "lock".to_string(), ..Request::default() }) {
+        Some(res) => {
+            res?;
+            println!("Agent wiped the vaults from memory and stopped.");
+        },
+        None => println!("No agent is running.")
+    }
+    Ok(())
+}
+
+// By the canonical path, the same file can be given in many ways
+fn open_vaults(files: &[&String], source: &PasswordSource) -> CommandResult<BTreeMap<String, DatabaseInFile>> {
+    let mut vaults = BTreeMap::new();
+    for file in files {
+        let path = match fs::canonicalize(file) {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(_) => return Err(CommandError::FileNotFound(file.to_string()))
+        };
+        if vaults.contains_key(&path) {
+            continue;
+        }
+        println!("Unlocking {}", file);
+        let file_db = unlock(path.clone(), source)?;
+        vaults.insert(path, file_db);
+    }
+    Ok(vaults)
+}
+
+// Read-only, the agent must not keep others from editing the vault
+fn unlock(path: String, source: &PasswordSource) -> CommandResult<DatabaseInFile> {
+    let mut attempts_left = match *source {
+        PasswordSource::Prompt => MAX_PASS_ATTEMPTS,
+        _ => 1
+    };
+    loop {
+        let password = source.read()?;
+        attempts_left -= 1;
+
+        match DatabaseInFile::open(path.clone(), &password) {
+            Ok(file_db) => return Ok(file_db),
+            Err(DbError::WrongPassword) if attempts_left > 0 => {
+                println!("Wrong master password (or the file is corrupted), try again.");
+            },
+            Err(why) => return Err(CommandError::Db(why))
+        }
+    }
+}
+
+// 0 means no limit
+fn parse_minutes(value: Option<&String>) -> CommandResult<Option<u64>> {
+    match value.map(|value| value.parse::<u64>()) {
+        Some(Ok(0)) => Ok(None),
+        Some(Ok(minutes)) => Ok(Some(minutes)),
+        _ => Err(CommandError::Usage(USAGE))
+    }
+}
@@ -0,0 +1,28 @@
+use db::DatabaseInFile;
+use commands::error::{CommandError, CommandResult};
+use timestamp;
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass backups";
+
+pub fn call(file_db: &DatabaseInFile, params: &[&str]) -> CommandResult<()>{
+    if !params.is_empty() {
+        return Err(CommandError::Usage(USAGE));
+    }
+
+    let backups = file_db.backups();
+    if backups.is_empty() {
+        println!("No backups found.");
+        return Ok(());
+    }
+
+    println!("{0: <3} | {1: <19} | Entries", "#", "Saved");
+    for backup in backups.iter() {
+        let entries = match file_db.db.open_with_same_key(&backup.path) {
+            Ok(db) => db.entries.len().to_string(),
+            Err(_) => "locked (different master password)".to_string()
+        };
+        println!("{0: <3} | {1: <19} | {2}", backup.number, timestamp::format(backup.modified), entries);
+    }
+    Ok(())
+}
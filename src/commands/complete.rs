@@ -0,0 +1,99 @@
+//!
+//! TAB completion of the interactive mode.
+//!
+//! The first word completes to a command name, every other word to an entry
+//! title, an entry path or a group path. Words that are not a prefix of
+//! anything are matched fuzzily, so `gthb` still completes to `GitHub`.
+//!
+
+use std::cmp;
+use std::collections::BTreeSet;
+use db::Database;
+use readline::Completer;
+use search::fuzzy_score;
+
+/// Commands available in the interactive mode.
+pub static COMMANDS: &[&str] = &[
+    "list", "new", "show", "copy", "edit", "remove", "passwd", "backups", "restore",
+    "settings", "stale", "history", "restore-password", "trash", "mkdir", "mv", "rmdir",
+    "search", "generate", "passphrase", "lock", "exit", "quit"
+];
+
+pub struct DbCompleter<'a> {
+    db: &'a Database
+}
+
+impl<'a> DbCompleter<'a> {
+    pub fn new(db: &'a Database) -> DbCompleter<'a> {
+        DbCompleter { db }
+    }
+
+    fn names(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        for entry in &self.db.entries {
+            names.insert(entry.title.clone());
+            if !entry.group.is_empty() {
+                names.insert(entry.path());
+            }
+        }
+        for group in self.db.all_groups() {
+            names.insert(format!("{}/", group));
+        }
+        names
+    }
+}
+
+impl<'a> Completer for DbCompleter<'a> {
+    fn complete(&self, line: &str, word: &str) -> Vec<String> {
+        let names: Vec<String> = if line.trim().is_empty() {
+            COMMANDS.iter().map(|name| name.to_string()).collect()
+        } else {
+            self.names().into_iter().collect()
+        };
+        matching(names, word)
+    }
+}
+
+/// Names starting with the word (ignoring case), or the fuzzy matches sorted
+/// by score if there are none.
+fn matching(names: Vec<String>, word: &str) -> Vec<String> {
+    let word_lower = word.to_ascii_lowercase();
+    let prefixed: Vec<String> = names.iter()
+        .filter(|name| name.to_ascii_lowercase().starts_with(&word_lower))
+        .cloned()
+        .collect();
+    if !prefixed.is_empty() || word.is_empty() {
+        return prefixed;
+    }
+
+    let mut scored: Vec<(u32, String)> = names.into_iter()
+        .filter_map(|name| fuzzy_score(word, &name).map(|score| (score, name)))
+        .collect();
+    // Stable sort, equal scores stay in alphabetical order
+    scored.sort_by_key(|&(score, _)| cmp::Reverse(score));
+    scored.into_iter().map(|(_, name)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DbCompleter;
+    use db::{Database, Entry};
+    use readline::Completer;
+
+    #[test]
+    fn test_complete() {
+        let mut db = Database::empty("test");
+        db.entries.push(Entry::new("GitHub", "alice", "pass"));
+        let mut gitlab = Entry::new("gitlab", "alice", "pass");
+        gitlab.group = "work".to_string();
+        db.entries.push(gitlab);
+
+        let completer = DbCompleter::new(&db);
+        assert_eq!(completer.complete("", "sh"), vec!["show".to_string()]);
+        assert_eq!(completer.complete("show ", "git"),
+                   vec!["GitHub".to_string(), "gitlab".to_string()]);
+        assert_eq!(completer.complete("show ", "wo"),
+                   vec!["work/".to_string(), "work/gitlab".to_string()]);
+        assert_eq!(completer.complete("show ", "gthb"), vec!["GitHub".to_string()]);
+    }
+}
@@ -1,17 +1,93 @@
-use db::DatabaseInFile;
+use std::io;
+use db::{DatabaseInFile, Entry};
+use commands;
+use clipboard::{self, Clipboard, DEFAULT_CLEAR_TIMEOUT};
+use readline;
+use commands::error::{CommandError, CommandResult};
+use commands::input::choose_entry;
 
-static USAGE: &'static str = "Invalid arguments.
-Usage: rpass copy <entry>";
+static USAGE: &str = "Invalid arguments.
+Usage: rpass copy <entry> [clear_after_seconds]";
 
-pub fn call(file_db: &Box<DatabaseInFile>, params: &[&str]){
-    if params.len() == 0 {
-        println!("{}", USAGE);
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    copy(file_db, params, false)
+}
+
+/// Same as `call`, but returns only after the clipboard has been cleared.
+/// One-shot invocations would otherwise exit before the clearing thread runs.
+pub fn call_and_wait(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    copy(file_db, params, true)
+}
+
+fn copy(file_db: &mut Box<DatabaseInFile>, params: &[&str], wait: bool) -> CommandResult<()>{
+    let timeout = parse_timeout(params)?;
+    let mut backend = detect_clipboard()?;
+
+    let entry_id = choose_entry(&file_db.db, params[0])?;
+    let digest = match copy_password(file_db.db.get_mut(&entry_id).unwrap(), &mut *backend) {
+        Ok(digest) => digest,
+        Err(why) => return Err(CommandError::Clipboard(format!("{}", why)))
+    };
+    commands::record_use(file_db, &entry_id);
+    clear_later(backend, digest, timeout, wait);
+    Ok(())
+}
+
+/// Put the plaintext password to the clipboard, it's wiped from memory again right after.
+/// Returns digest of the password, so the clipboard can be cleared later
+/// only if it wasn't overwritten in the meantime.
+pub fn copy_password(entry: &mut Entry, clipboard: &mut dyn Clipboard) -> io::Result<[u8; 32]> {
+    entry.inspect_password(|password| {
+        clipboard.set(password)?;
+        Ok(clipboard::digest(password))
+    })
+}
+
+/// Seconds after which the clipboard is cleared, the optional second parameter.
+pub fn parse_timeout(params: &[&str]) -> CommandResult<u64> {
+    match params.len() {
+        1 => Ok(DEFAULT_CLEAR_TIMEOUT),
+        2 => params[1].parse::<u64>().map_err(|_| CommandError::Usage(USAGE)),
+        _ => Err(CommandError::Usage(USAGE))
+    }
+}
+
+pub fn detect_clipboard() -> CommandResult<Box<dyn Clipboard + Send>> {
+    match clipboard::detect() {
+        Some(backend) => Ok(backend),
+        None => Err(CommandError::Clipboard(
+            "no clipboard available (neither X11 nor Wayland display is set)".to_string()))
+    }
+}
+
+/// Tell the user and clear the password with the digest after `timeout` seconds, unless it's 0.
+pub fn clear_later(backend: Box<dyn Clipboard + Send>, digest: [u8; 32], timeout: u64, wait: bool) {
+    if timeout == 0 {
+        println!("Password copied to clipboard.");
         return;
     }
 
-    let entry_title = params[0];
-    match file_db.db.get(entry_title) {
-        Some(entry) => entry.copy_pass_to_clipboard(),
-        None => println!("No entry named '{}' was found.", entry_title)
+    clipboard::schedule_clear(backend, digest, timeout);
+    println!("Password copied to clipboard, it will be cleared in {} seconds.", timeout);
+    if wait {
+        // Ctrl-C clears it at once
+        clipboard::wait_pending(readline::interrupted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use db::Entry;
+    use clipboard::{self, MemoryClipboard};
+    use super::copy_password;
+
+    #[test]
+    fn test_copy_password() {
+        let mut entry = Entry::new("service", "name", "pass");
+        let mut clipboard = MemoryClipboard::new();
+
+        let digest = copy_password(&mut entry, &mut clipboard).unwrap();
+        assert_eq!(clipboard.content, "pass");
+        assert_eq!(digest, clipboard::digest("pass"));
     }
 }
@@ -1,64 +1,66 @@
-use rpassword::read_password;
+use readline::read_password;
+use std::io;
 use std::path::Path;
 use db::{Database, DatabaseInFile};
+use db::lock::{self, LockStatus};
+use commands::error::{CommandError, CommandResult};
+use commands::kdf::parse_kdf_options;
 
-static USAGE: &'static str = "Invalid arguments.\n
-Usage: rpass create <filename>";
-static NEW_PASS: &'static str = "Please enter the master password:";
-static NEW_PASS_CONFIRM: &'static str = "Confirm the password:";
+static USAGE: &str = "Invalid arguments.\n
+Usage: rpass create <filename> [--kdf argon2id|scrypt] [--memory <MiB>] [--iterations <n>]
+                               [--parallelism <n>] [--log-n <n>]";
+static NEW_PASS: &str = "Please enter the master password:";
+static NEW_PASS_CONFIRM: &str = "Confirm the password:";
 
 const PASS_MIN_LENGTH: usize = 2;
 
-fn usage(){
-	println!("{}", USAGE);
-}
-
-pub fn call(params: &[String]) -> Option<Box<DatabaseInFile>>{
-	if params.len() != 1 {
-		usage();
-		return None;
-	} else {
-		let db_path = Path::new(&params[0]);
+pub fn call(params: &[String]) -> CommandResult<Box<DatabaseInFile>>{
+	if params.is_empty() {
+		return Err(CommandError::Usage(USAGE));
+	}
+	let db_path = Path::new(&params[0]);
+	let options: Vec<&str> = params[1..].iter().map(|s| s.as_ref()).collect();
+	let kdf = parse_kdf_options(&options)?;
 
-		if db_path.exists(){
-			println!("File at path '{}' already exists, cannot create new database.", &params[0]);
-			return None;
-		}
+	if db_path.exists(){
+		return Err(CommandError::InvalidInput(
+			format!("File at path '{}' already exists, cannot create new database.", &params[0])));
+	}
 
-		let pass = get_pass();
-		let database = Database::empty(pass.as_ref());
+	let pass = get_pass()?;
+	println!("Deriving the key with {}...", kdf.describe());
+	let database = Database::empty_with_kdf(pass.as_ref(), kdf)?;
 
-		return match database.save_to_file(&db_path) {
-			Ok(_) => Some(Box::new(
-				DatabaseInFile{
-					db: database,
-					filepath: params[0].clone()
-				}
-			)),
-			Err(why) => {
-				println!("Error creating file, reason: {}", why);
-				None
-			}
-		}
+	let lock = match lock::acquire(db_path)? {
+		LockStatus::Acquired(lock) => lock,
+		LockStatus::HeldBy(owner) => return Err(CommandError::InvalidInput(
+			format!("Database file is being created by process {}.", owner)))
+	};
+	// Somebody could have been faster while we were deriving the key
+	if db_path.exists(){
+		return Err(CommandError::InvalidInput(
+			format!("File at path '{}' already exists, cannot create new database.", &params[0])));
 	}
+	Ok(Box::new(DatabaseInFile::create(params[0].clone(), database, lock)?))
 }
 
-fn get_pass() -> String{
+/// Ask for a new master password twice, until both inputs match.
+pub fn get_pass() -> io::Result<String>{
 	loop {
 		println!("Please enter new master password:");
-		let password = read_password().unwrap();
+		let password = read_password()?;
 		if password.len() < PASS_MIN_LENGTH {
 			println!("Password is too short (has to be at least 10 characters).");
 			continue;
 		}
 
 		println!("{}", NEW_PASS_CONFIRM);
-		let password2 = read_password().unwrap();
+		let password2 = read_password()?;
 
 		if password != password2 {
 			println!("Passwords are not the same.");
 			continue;
 		}
-		return password;
+		return Ok(password);
 	}
 }
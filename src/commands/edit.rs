@@ -1,16 +1,204 @@
-use db::DatabaseInFile;
+use db::{DatabaseInFile, Entry};
+use db::entry::{CustomField, is_reserved_field};
+use commands;
+use secstr::SecStr;
+use generate::{self, PasswordPolicy};
+use commands::error::{CommandError, CommandResult};
+use commands::generate::parse_generate_option;
+use commands::input::{read_line, read_lines, read_new_password, read_custom_field, parse_tags, confirm, choose_entry};
+use std::collections::BTreeSet;
+use std::io;
 
-static USAGE: &'static str = "Invalid arguments.
-Usage: rpass edit <entry>";
+static USAGE: &str = "Invalid arguments.
+Usage: rpass edit <entry> [--generate [generator options]]";
 
-fn usage(){
-    println!("{}", USAGE);
+/// Typed instead of a value to clear an optional field.
+const CLEAR: &str = "-";
+
+/// New values for an entry, None means keep the current value.
+struct EntryChanges {
+    title: Option<String>,
+    username: Option<String>,
+    password: Option<SecStr>,
+    url: Option<String>,
+    notes: Option<String>,
+    tags: Option<BTreeSet<String>>,
+    fields: Vec<FieldChange>
+}
+
+enum FieldChange {
+    Set(CustomField),
+    Remove(String)
+}
+
+impl EntryChanges {
+    fn is_empty(&self) -> bool {
+        self.title.is_none() && self.username.is_none() && self.password.is_none() &&
+            self.url.is_none() && self.notes.is_none() && self.tags.is_none() && self.fields.is_empty()
+    }
+
+    fn apply(self, entry: &mut Entry, history_length: usize) {
+        if let Some(title) = self.title {
+            entry.title = title;
+        }
+        if let Some(username) = self.username {
+            entry.set_username(username);
+        }
+        if let Some(password) = self.password {
+            entry.set_password(password, history_length);
+        }
+        if let Some(url) = self.url {
+            entry.url = url;
+        }
+        if let Some(notes) = self.notes {
+            entry.notes = notes;
+        }
+        if let Some(tags) = self.tags {
+            entry.tags = tags;
+        }
+        for change in self.fields {
+            match change {
+                FieldChange::Set(field) => entry.set_field(field),
+                FieldChange::Remove(name) => { entry.remove_field(&name); }
+            }
+        }
+    }
+}
+
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    if params.is_empty() {
+        return Err(CommandError::Usage(USAGE));
+    }
+
+    let policy = parse_generate_option(&params[1..])?;
+    let entry_id = choose_entry(&file_db.db, params[0])?;
+    let changes = read_changes(file_db, &entry_id, policy)?;
+
+    // Keeping a conflicting entry as it is still resolves the conflict
+    let resolves_conflict = file_db.db.get(&entry_id).unwrap().conflict;
+    if changes.is_empty() && !resolves_conflict {
+        println!("Nothing changed.");
+        return Ok(());
+    }
+
+    if !confirm("Save changes?")? {
+        println!("Changes discarded.");
+        return Ok(());
+    }
+
+    let new_title = {
+        let history_length = file_db.db.settings.history_length;
+        let entry = file_db.db.get_mut(&entry_id).unwrap();
+        changes.apply(entry, history_length);
+        entry.mark_modified();
+        // Editing is how a conflict from merging gets resolved
+        entry.conflict = false;
+        entry.title.clone()
+    };
+
+    commands::save(file_db)?;
+    println!("Entry '{}' was successfully updated.", new_title);
+    Ok(())
+}
+
+fn read_changes(file_db: &DatabaseInFile, entry_id: &str, policy: Option<PasswordPolicy>)
+    -> CommandResult<EntryChanges>
+{
+    let entry = file_db.db.get(entry_id).unwrap();
+    println!("Press Enter to keep the current value, '{}' clears an optional one.", CLEAR);
+
+    let title = read_title(file_db, &entry.group, &entry.title)?;
+
+    let input = read_line(&format!("Username [{}]: ", entry.username()))?;
+    let username = if input.is_empty() || input == entry.username() { None } else { Some(input) };
+
+    let password = match policy {
+        Some(policy) => Some(generate::generate(&policy)?),
+        None => read_new_password("Password [unchanged]: ")?.map(SecStr::new)
+    };
+
+    let url = read_optional(&format!("URL [{}]: ", entry.url), &entry.url)?;
+
+    if !entry.notes.is_empty() {
+        println!("Notes:\n{}", entry.notes);
+    }
+    let notes = read_optional("Notes (finish with an empty line): ", &entry.notes)?;
+    let notes = match notes {
+        // Only the first line was read so far
+        Some(first) => if first.is_empty() { Some(first) } else {
+            let rest = read_lines("")?;
+            Some(if rest.is_empty() { first } else { first + "\n" + &rest })
+        },
+        None => None
+    };
+
+    let tags = read_optional(&format!("Tags [{}]: ", entry.tags_string()), &entry.tags_string())?
+        .map(|input| parse_tags(&input));
+
+    Ok(EntryChanges {
+        title,
+        username,
+        password,
+        url,
+        notes,
+        tags,
+        fields: read_field_changes(entry)?
+    })
+}
+
+// Enter keeps the current value, CLEAR gives an empty one
+fn read_optional(prompt: &str, current: &str) -> io::Result<Option<String>> {
+    let input = read_line(prompt)?;
+    Ok(if input.is_empty() || input == current {
+        None
+    } else if input == CLEAR {
+        Some(String::new())
+    } else {
+        Some(input)
+    })
+}
+
+// Custom fields are changed one by one, naming an existing field changes or removes it
+fn read_field_changes(entry: &Entry) -> io::Result<Vec<FieldChange>> {
+    if !entry.fields.is_empty() {
+        let names: Vec<&str> = entry.fields.iter().map(|field| &field.name[..]).collect();
+        println!("Custom fields: {}", names.join(", "));
+    }
+    let mut changes = Vec::new();
+    loop {
+        let name = read_line("Custom field to add or change (Enter to finish): ")?;
+        if name.is_empty() {
+            return Ok(changes);
+        }
+        if is_reserved_field(&name) {
+            println!("'{}' is not a custom field.", name);
+            continue;
+        }
+        let secret = match entry.field(&name) {
+            Some(field) => {
+                if confirm(&format!("Remove field '{}'?", name))? {
+                    changes.push(FieldChange::Remove(name));
+                    continue;
+                }
+                field.is_secret()
+            },
+            None => confirm("Is the value secret?")?
+        };
+        changes.push(FieldChange::Set(read_custom_field(&name, secret)?));
+    }
 }
 
-pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]){
-    if params.len() == 0 {
-        usage();
-        return;
+// Read the new title, a title already used by another entry in the group has to be confirmed
+fn read_title(file_db: &DatabaseInFile, group: &str, current: &str) -> io::Result<Option<String>> {
+    loop {
+        let input = read_line(&format!("Title [{}]: ", current))?;
+        if input.is_empty() || input == current {
+            return Ok(None);
+        }
+        if file_db.db.has_title(group, &input) &&
+            !confirm(&format!("Entry named '{}' already exists, use the title anyway?", input))? {
+            continue;
+        }
+        return Ok(Some(input));
     }
-    println!("Error: NOT IMPLEMENTED YET");
 }
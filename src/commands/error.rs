@@ -0,0 +1,119 @@
+use std::error;
+use std::fmt;
+use std::io;
+use db::DbError;
+use generate::GenerateError;
+use passphrase::PassphraseError;
+use search::SearchError;
+use shellwords::SplitError;
+
+/// Process exit codes of one-shot commands, so that scripts can tell the failures apart.
+pub const EXIT_IO: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_WRONG_PASSWORD: i32 = 3;
+pub const EXIT_ENTRY_NOT_FOUND: i32 = 4;
+pub const EXIT_FILE_NOT_FOUND: i32 = 5;
+pub const EXIT_CORRUPTED: i32 = 6;
+pub const EXIT_INVALID_INPUT: i32 = 7;
+pub const EXIT_CLIPBOARD: i32 = 8;
+pub const EXIT_LOCKED: i32 = 9;
+pub const EXIT_CONFLICT: i32 = 10;
+
+#[derive(Debug)]
+pub enum CommandError {
+    /// Bad arguments, holds the usage text of the command.
+    Usage(&'static str),
+    /// No entry with the given title.
+    EntryNotFound(String),
+    /// No database file at the given path.
+    FileNotFound(String),
+    /// The user entered something we can't accept.
+    InvalidInput(String),
+    Clipboard(String),
+    Db(DbError),
+    Io(io::Error)
+}
+
+impl CommandError {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            CommandError::Usage(_) => EXIT_USAGE,
+            CommandError::EntryNotFound(_) => EXIT_ENTRY_NOT_FOUND,
+            CommandError::FileNotFound(_) => EXIT_FILE_NOT_FOUND,
+            CommandError::InvalidInput(_) => EXIT_INVALID_INPUT,
+            CommandError::Clipboard(_) => EXIT_CLIPBOARD,
+            CommandError::Db(DbError::WrongPassword) => EXIT_WRONG_PASSWORD,
+            CommandError::Db(DbError::Io(_)) => EXIT_IO,
+            CommandError::Db(DbError::ReadOnly) => EXIT_LOCKED,
+            CommandError::Db(DbError::ConcurrentModification) => EXIT_CONFLICT,
+            CommandError::Db(_) => EXIT_CORRUPTED,
+            CommandError::Io(_) => EXIT_IO
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommandError::Usage(usage) => write!(f, "{}", usage),
+            CommandError::EntryNotFound(ref title) => write!(f, "No entry named '{}' was found.", title),
+            CommandError::FileNotFound(ref path) => write!(f, "No such file '{}' exists.", path),
+            CommandError::InvalidInput(ref why) => write!(f, "{}", why),
+            CommandError::Clipboard(ref why) => write!(f, "Clipboard error: {}", why),
+            CommandError::Db(ref err) => write!(f, "Database error: {}", err),
+            CommandError::Io(ref err) => write!(f, "I/O error: {}", err)
+        }
+    }
+}
+
+impl error::Error for CommandError {
+    fn description(&self) -> &str {
+        match *self {
+            CommandError::Usage(_) => "invalid arguments",
+            CommandError::EntryNotFound(_) => "entry not found",
+            CommandError::FileNotFound(_) => "file not found",
+            CommandError::InvalidInput(_) => "invalid input",
+            CommandError::Clipboard(_) => "clipboard error",
+            CommandError::Db(_) => "database error",
+            CommandError::Io(_) => "I/O error"
+        }
+    }
+}
+
+impl From<DbError> for CommandError {
+    fn from(err: DbError) -> CommandError {
+        CommandError::Db(err)
+    }
+}
+
+impl From<GenerateError> for CommandError {
+    fn from(err: GenerateError) -> CommandError {
+        CommandError::InvalidInput(format!("Cannot generate password: {}.", err))
+    }
+}
+
+impl From<PassphraseError> for CommandError {
+    fn from(err: PassphraseError) -> CommandError {
+        CommandError::InvalidInput(format!("Cannot generate passphrase: {}.", err))
+    }
+}
+
+impl From<SearchError> for CommandError {
+    fn from(err: SearchError) -> CommandError {
+        CommandError::InvalidInput(format!("Invalid search: {}.", err))
+    }
+}
+
+impl From<SplitError> for CommandError {
+    fn from(err: SplitError) -> CommandError {
+        CommandError::InvalidInput(format!("Cannot parse the command: {}.", err))
+    }
+}
+
+impl From<io::Error> for CommandError {
+    fn from(err: io::Error) -> CommandError {
+        CommandError::Io(err)
+    }
+}
+
+pub type CommandResult<T> = Result<T, CommandError>;
@@ -0,0 +1,198 @@
+//!
+//! Shared output layer for commands printing entries.
+//!
+//! Every command printing entries goes through here, so that `--format` and
+//! `--reveal` behave the same everywhere.
+//!
+
+use std::collections::BTreeMap;
+use serde_json;
+use db::Entry;
+use commands::error::{CommandError, CommandResult};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Table,
+    Json,
+    Tsv
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "table" => Some(Format::Table),
+            "json" => Some(Format::Json),
+            "tsv" => Some(Format::Tsv),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OutputOptions {
+    pub format: Format,
+    /// Print secrets in plaintext instead of the mask.
+    pub reveal: bool
+}
+
+impl OutputOptions {
+    /// Take `--format <format>` and `--reveal` out of the command parameters.
+    /// Returns the options and the remaining parameters.
+    pub fn from_params<'a>(params: &[&'a str]) -> CommandResult<(OutputOptions, Vec<&'a str>)> {
+        let mut options = OutputOptions { format: Format::Table, reveal: false };
+        let mut rest = Vec::new();
+        let mut iter = params.iter();
+        while let Some(param) = iter.next() {
+            match *param {
+                "--reveal" => options.reveal = true,
+                "--format" => {
+                    let name = match iter.next() {
+                        Some(name) => *name,
+                        None => return Err(CommandError::InvalidInput("Missing value of --format.".to_string()))
+                    };
+                    options.format = match Format::parse(name) {
+                        Some(format) => format,
+                        None => return Err(CommandError::InvalidInput(
+                            format!("Unknown format '{}', use json, table or tsv.", name)))
+                    };
+                },
+                other => rest.push(other)
+            }
+        }
+        Ok((options, rest))
+    }
+}
+
+/// Print a list of entries, one row per entry with the summary columns.
+pub fn print_entries(entries: &[&Entry], options: &OutputOptions) {
+    let rows: Vec<Vec<(&'static str, String)>> = entries.iter()
+        .map(|entry| entry.summary_fields())
+        .collect();
+    print!("{}", format_rows(&rows, options.format));
+}
+
+/// Print rows of named values, for commands listing entries with extra columns.
+pub fn print_rows<S: AsRef<str>>(rows: &[Vec<(S, String)>], options: &OutputOptions) {
+    print!("{}", format_rows(rows, options.format));
+}
+
+/// Print all details of a single entry.
+pub fn print_entry(entry: &mut Entry, options: &OutputOptions) {
+    print_record(&entry.detail_fields(options.reveal), options);
+}
+
+/// Print named values of a single entry, as given by `Entry::detail_fields`.
+pub fn print_record(fields: &[(String, String)], options: &OutputOptions) {
+    print!("{}", format_record(fields, options.format));
+}
+
+fn format_rows<S: AsRef<str>>(rows: &[Vec<(S, String)>], format: Format) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Table => {
+            let header: Vec<&str> = match rows.first() {
+                Some(row) => row.iter().map(|(name, _)| name.as_ref()).collect(),
+                None => vec!["id", "title", "username", "group"]
+            };
+            out.push_str(&table_row(&header.iter().map(|name| capitalize(name)).collect::<Vec<_>>()));
+            for row in rows {
+                out.push_str(&table_row(&row.iter().map(|(_, value)| value.clone()).collect::<Vec<_>>()));
+            }
+        },
+        Format::Tsv => {
+            for row in rows {
+                let values: Vec<String> = row.iter().map(|(_, value)| escape_tsv(value)).collect();
+                out.push_str(&values.join("\t"));
+                out.push('\n');
+            }
+        },
+        Format::Json => {
+            let objects: Vec<BTreeMap<String, String>> = rows.iter().map(|row| to_map(row)).collect();
+            out.push_str(&serde_json::to_string(&objects).unwrap());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn format_record(fields: &[(String, String)], format: Format) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Table => {
+            for (name, value) in fields {
+                // Continuation lines of multi-line values (notes) are indented under the value
+                let value = value.replace("\n", &format!("\n{0: <11}", ""));
+                out.push_str(&format!("{0: <10} {1: <10}\n", capitalize(name) + ":", value));
+            }
+        },
+        Format::Tsv => {
+            for (name, value) in fields {
+                out.push_str(&format!("{}\t{}\n", escape_tsv(name), escape_tsv(value)));
+            }
+        },
+        Format::Json => {
+            let map: BTreeMap<String, String> = fields.iter().cloned().collect();
+            out.push_str(&serde_json::to_string(&map).unwrap());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn table_row(values: &[String]) -> String {
+    let cells: Vec<String> = values.iter().map(|value| format!("{0: <10}", value)).collect();
+    cells.join(" | ") + "\n"
+}
+
+fn to_map<S: AsRef<str>>(fields: &[(S, String)]) -> BTreeMap<String, String> {
+    fields.iter().map(|(name, value)| (name.as_ref().to_string(), value.clone())).collect()
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new()
+    }
+}
+
+// Tabs and newlines would break the columns
+fn escape_tsv(value: &str) -> String {
+    value.replace("\\", "\\\\").replace("\t", "\\t").replace("\n", "\\n").replace("\r", "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Format, OutputOptions, format_record, format_rows};
+    use db::Entry;
+    use db::entry::MASK;
+
+    #[test]
+    fn test_options_from_params() {
+        let (options, rest) = OutputOptions::from_params(&["github", "--format", "json", "--reveal"]).unwrap();
+        assert_eq!(options.format, Format::Json);
+        assert!(options.reveal);
+        assert_eq!(rest, vec!["github"]);
+
+        assert!(OutputOptions::from_params(&["--format", "xml"]).is_err());
+        assert!(OutputOptions::from_params(&["--format"]).is_err());
+    }
+
+    #[test]
+    fn test_password_masked() {
+        let mut entry = Entry::new("service", "name", "pass");
+        let out = format_record(&entry.detail_fields(false), Format::Json);
+        assert!(!out.contains("pass\""));
+        assert!(out.contains(MASK));
+
+        let out = format_record(&entry.detail_fields(true), Format::Json);
+        assert!(out.contains("\"password\":\"pass\""));
+    }
+
+    #[test]
+    fn test_tsv_escaping() {
+        let entry = Entry::new("a\tb", "line\nbreak", "pass");
+        let out = format_rows(&[entry.summary_fields()], Format::Tsv);
+        assert_eq!(out, format!("{}\ta\\tb\tline\\nbreak\t\n", entry.short_id()));
+    }
+}
@@ -0,0 +1,50 @@
+use generate::{self, PasswordPolicy};
+use commands::error::{CommandError, CommandResult};
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass generate [--length <n>] [--min-each <n>] [--no-lower] [--no-upper] [--no-digits] [--no-symbols] [--exclude-ambiguous]";
+
+pub fn call(params: &[&str]) -> CommandResult<()>{
+    let policy = parse_policy(params)?;
+    let mut password = generate::generate(&policy)?;
+    password.unlock();
+    println!("{}", password.string);
+    password.delete();
+    Ok(())
+}
+
+/// Build the generator policy from command parameters, unknown parameters are an error.
+pub fn parse_policy(params: &[&str]) -> CommandResult<PasswordPolicy>{
+    let mut policy = PasswordPolicy::new();
+    let mut iter = params.iter();
+    while let Some(param) = iter.next() {
+        match *param {
+            "--length" => policy.length = parse_number(iter.next())?,
+            "--min-each" => policy.min_each = parse_number(iter.next())?,
+            "--no-lower" => policy.lowercase = false,
+            "--no-upper" => policy.uppercase = false,
+            "--no-digits" => policy.digits = false,
+            "--no-symbols" => policy.symbols = false,
+            "--exclude-ambiguous" => policy.exclude_ambiguous = true,
+            _ => return Err(CommandError::Usage(USAGE))
+        }
+    }
+    Ok(policy)
+}
+
+/// Parameters of `new`/`edit` asking for a generated password, i.e. `--generate`
+/// followed by the generator options. Returns None if the password should be typed.
+pub fn parse_generate_option(params: &[&str]) -> CommandResult<Option<PasswordPolicy>>{
+    match params.first() {
+        None => Ok(None),
+        Some(&"--generate") => parse_policy(&params[1..]).map(Some),
+        Some(_) => Err(CommandError::Usage(USAGE))
+    }
+}
+
+fn parse_number(value: Option<&&str>) -> CommandResult<usize>{
+    match value.and_then(|value| value.parse().ok()) {
+        Some(number) => Ok(number),
+        None => Err(CommandError::Usage(USAGE))
+    }
+}
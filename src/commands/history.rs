@@ -0,0 +1,34 @@
+use db::DatabaseInFile;
+use db::entry::{format_time, MASK};
+use commands::error::{CommandError, CommandResult};
+use commands::format::{self, OutputOptions};
+use commands::input::choose_entry;
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass history <entry> [--format json|table|tsv] [--reveal]";
+
+/// List previous passwords of an entry, masked unless --reveal is given.
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    let (options, params) = OutputOptions::from_params(params)?;
+    if params.len() != 1 {
+        return Err(CommandError::Usage(USAGE));
+    }
+
+    let entry_id = choose_entry(&file_db.db, params[0])?;
+    let entry = file_db.db.get_mut(&entry_id).unwrap();
+    if entry.history.is_empty() {
+        println!("Entry '{}' has no previous passwords.", entry.title);
+        return Ok(());
+    }
+
+    let rows: Vec<Vec<(&'static str, String)>> = entry.history.iter_mut()
+        .enumerate()
+        .map(|(index, previous)| vec![
+            ("number", (index + 1).to_string()),
+            ("replaced", format_time(previous.replaced, "unknown")),
+            ("password", if options.reveal { previous.reveal() } else { MASK.to_string() })
+        ])
+        .collect();
+    format::print_rows(&rows, &options);
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use std::cmp;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write, Error, ErrorKind};
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use db::{Database, Entry};
+use readline::{self, read_password};
+use db::entry::CustomField;
+use secstr::SecStr;
+use search::fuzzy_score;
+use commands::error::{CommandError, CommandResult};
+
+/// Print the prompt and read one line from stdin, without the trailing newline.
+/// Fails after `readline::prompt_timeout` without input.
+pub fn read_line(prompt: &str) -> io::Result<String> {
+	print!("{}", prompt);
+	io::stdout().flush()?;
+	readline::wait_for_input(readline::prompt_timeout())?;
+	let mut input = String::new();
+	io::stdin().read_line(&mut input)?;
+	Ok(input.trim().to_string())
+}
+
+/// Ask a yes/no question, anything else than 'y' or 'yes' means no.
+pub fn confirm(question: &str) -> io::Result<bool> {
+	let answer = read_line(&format!("{} [y/N]: ", question))?;
+	Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+}
+
+/// Read a new password twice without echo. Returns None if the user entered
+/// an empty password, i.e. wants to keep the old one.
+pub fn read_new_password(prompt: &str) -> io::Result<Option<String>> {
+	loop {
+		print!("{}", prompt);
+		io::stdout().flush()?;
+		let password = read_password()?;
+		if password.is_empty() {
+			return Ok(None);
+		}
+
+		print!("Confirm the password: ");
+		io::stdout().flush()?;
+		let password2 = read_password()?;
+		if password != password2 {
+			println!("Passwords are not the same.");
+			continue;
+		}
+		return Ok(Some(password));
+	}
+}
+
+/// Read lines until an empty one, the first line is read after the prompt.
+pub fn read_lines(prompt: &str) -> io::Result<String> {
+	let mut lines: Vec<String> = Vec::new();
+	let mut line = read_line(prompt)?;
+	while !line.is_empty() {
+		lines.push(line);
+		line = read_line("")?;
+	}
+	Ok(lines.join("\n"))
+}
+
+/// Tags separated by commas or whitespace.
+pub fn parse_tags(input: &str) -> BTreeSet<String> {
+	input.split(|c: char| c == ',' || c.is_whitespace())
+		.filter(|tag| !tag.is_empty())
+		.map(|tag| tag.to_string())
+		.collect()
+}
+
+/// Read the value of a custom field, a secret one without echo.
+pub fn read_custom_field(name: &str, secret: bool) -> io::Result<CustomField> {
+	if secret {
+		print!("Value of '{}': ", name);
+		io::stdout().flush()?;
+		Ok(CustomField::secret(name.to_string(), SecStr::new(read_password()?)))
+	} else {
+		let value = read_line(&format!("Value of '{}': ", name))?;
+		Ok(CustomField::plain(name.to_string(), value))
+	}
+}
+
+/// Resolve a title or an id prefix given by the user to the id of one entry.
+/// When more entries match, the user picks one of them. Without an exact match
+/// titles starting with the query and then fuzzy matches are offered, a single
+/// one has to be confirmed.
+pub fn choose_entry(db: &Database, query: &str) -> CommandResult<String> {
+	let matches = db.find(query);
+	if !matches.is_empty() {
+		return choose_from(matches, query);
+	}
+	let matches = approximate_matches(db, query);
+	if matches.len() == 1 {
+		// Not to mix with the output of the command
+		let _ = writeln!(io::stderr(), "Entry '{}' matches '{}'.", matches[0].path(), query);
+		if !confirm("Use it?")? {
+			return Err(CommandError::EntryNotFound(query.to_string()));
+		}
+	}
+	choose_from(matches, query)
+}
+
+/// Same as `choose_entry` without the approximate matches, for scripts and for
+/// changes that shouldn't happen to a guessed entry.
+pub fn choose_exact_entry(db: &Database, query: &str) -> CommandResult<String> {
+	choose_from(db.find(query), query)
+}
+
+fn approximate_matches<'a>(db: &'a Database, query: &str) -> Vec<&'a Entry> {
+	let query_lower = query.to_ascii_lowercase();
+	let prefixed: Vec<&Entry> = db.entries.iter()
+		.filter(|entry| entry.title.to_ascii_lowercase().starts_with(&query_lower))
+		.collect();
+	if !prefixed.is_empty() {
+		return prefixed;
+	}
+
+	let mut scored: Vec<(u32, &Entry)> = db.entries.iter()
+		.filter_map(|entry| fuzzy_score(query, &entry.path()).map(|score| (score, entry)))
+		.collect();
+	scored.sort_by_key(|&(score, _)| cmp::Reverse(score));
+	scored.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Same as `choose_entry` for entries in the trash.
+pub fn choose_trashed_entry(db: &Database, query: &str) -> CommandResult<String> {
+	choose_from(db.find_in_trash(query), query)
+}
+
+fn choose_from(matches: Vec<&Entry>, query: &str) -> CommandResult<String> {
+	match matches.len() {
+		0 => return Err(CommandError::EntryNotFound(query.to_string())),
+		1 => return Ok(matches[0].id.clone()),
+		_ => ()
+	}
+
+	println!("There are {} entries matching '{}':", matches.len(), query);
+	for (number, entry) in matches.iter().enumerate() {
+		println!("{0: >3}) {1}  {2: <20} {3}", number + 1, entry.short_id(), entry.title, entry.username());
+	}
+	let answer = read_line(&format!("Choose one [1-{}]: ", matches.len()))?;
+	match answer.parse::<usize>() {
+		Ok(number) if number >= 1 && number <= matches.len() => Ok(matches[number - 1].id.clone()),
+		_ => Err(CommandError::InvalidInput("No entry was chosen.".to_string()))
+	}
+}
+
+/// Where to take the master password from.
+pub enum PasswordSource {
+	/// Ask on the terminal without echo.
+	Prompt,
+	/// Read the next line from an already opened file descriptor, which stays open.
+	Fd(RawFd),
+	/// Take the value of an environment variable.
+	Env(String)
+}
+
+// Byte by byte, a buffer would swallow the lines of the next reads
+fn read_fd_line(mut file: &File, fd: RawFd) -> io::Result<String> {
+	let mut line = Vec::new();
+	let mut ended = false;
+	let mut byte = [0u8];
+	loop {
+		match file.read(&mut byte) {
+			Ok(0) => break,
+			Ok(_) if byte[0] == b'\n' => {
+				ended = true;
+				break;
+			},
+			Ok(_) => line.push(byte[0]),
+			Err(ref why) if why.kind() == ErrorKind::Interrupted => (),
+			Err(why) => return Err(why)
+		}
+	}
+	if !ended && line.is_empty() {
+		return Err(Error::new(ErrorKind::UnexpectedEof, format!("no password left in file descriptor {}", fd)));
+	}
+	if line.last() == Some(&b'\r') {
+		line.pop();
+	}
+	String::from_utf8(line).map_err(|_| Error::new(ErrorKind::InvalidData, "password is not valid UTF-8"))
+}
+
+impl PasswordSource {
+	pub fn read(&self) -> io::Result<String> {
+		match *self {
+			PasswordSource::Prompt => {
+				// Not to end up in the output captured by scripts
+				let _ = writeln!(io::stderr(), "Please enter master password:");
+				read_password()
+			},
+			PasswordSource::Fd(fd) => {
+				let file = unsafe { File::from_raw_fd(fd) };
+				let res = read_fd_line(&file, fd);
+				// The descriptor belongs to the caller, dropping the file would close it
+				let _ = file.into_raw_fd();
+				res
+			},
+			PasswordSource::Env(ref name) => env::var(name).map_err(|_|
+				Error::new(ErrorKind::NotFound, format!("environment variable {} is not set", name)))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env;
+	use std::fs::{self, File};
+	use std::io::Write;
+	use std::os::unix::io::AsRawFd;
+	use super::PasswordSource;
+
+	#[test]
+	fn test_fd_source() {
+		let path = env::temp_dir().join(format!("rpass-test-fd-{}", unsafe { ::libc::getpid() }));
+		File::create(&path).unwrap().write_all(b"first\r\n\nthird").unwrap();
+		let file = File::open(&path).unwrap();
+		let source = PasswordSource::Fd(file.as_raw_fd());
+
+		// Every read takes the next line and leaves the descriptor open
+		assert_eq!(source.read().unwrap(), "first");
+		assert_eq!(source.read().unwrap(), "");
+		assert_eq!(source.read().unwrap(), "third");
+		assert!(source.read().is_err());
+		assert!(file.metadata().is_ok());
+
+		drop(file);
+		fs::remove_file(&path).unwrap();
+	}
+}
@@ -0,0 +1,160 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use db::kdf::{KdfParams, random_salt, ARGON2_MEMORY_KIB, ARGON2_ITERATIONS, ARGON2_PARALLELISM,
+              SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P};
+use commands::error::{CommandError, CommandResult};
+
+static USAGE: &str = "Invalid arguments.
+Key derivation options:
+    --kdf argon2id|scrypt (default argon2id)
+    --memory <MiB> --iterations <n> --parallelism <n> (argon2id)
+    --log-n <n> (scrypt)";
+
+/// Time the key derivation should take when unlocking the database.
+const TARGET_MILLIS: u64 = 1000;
+const BENCHMARK_PASSWORD: &str = "benchmark password";
+
+/// Build KDF parameters with a fresh salt from the command line options,
+/// options not given keep their defaults. Bcrypt is left for reading old
+/// databases, its key is only 24 bytes and it ignores long passwords.
+pub fn parse_kdf_options(params: &[&str]) -> CommandResult<KdfParams>{
+    let mut kdf = "argon2id";
+    let mut memory_mib = None;
+    let mut iterations = None;
+    let mut parallelism = None;
+    let mut log_n = None;
+
+    let mut iter = params.iter();
+    while let Some(param) = iter.next() {
+        match *param {
+            "--kdf" => kdf = match iter.next() {
+                Some(name) => *name,
+                None => return Err(CommandError::Usage(USAGE))
+            },
+            "--memory" => memory_mib = Some(parse_number::<u32>(param, iter.next())?),
+            "--iterations" => iterations = Some(parse_number(param, iter.next())?),
+            "--parallelism" => parallelism = Some(parse_number(param, iter.next())?),
+            "--log-n" => log_n = Some(parse_number(param, iter.next())?),
+            _ => return Err(CommandError::Usage(USAGE))
+        }
+    }
+
+    let memory_kib = match memory_mib {
+        Some(mib) => match mib.checked_mul(1024) {
+            Some(kib) => kib,
+            None => return Err(CommandError::InvalidInput(format!("Too much memory: {} MiB.", mib)))
+        },
+        None => ARGON2_MEMORY_KIB
+    };
+    let params = match kdf {
+        "argon2id" => KdfParams::Argon2id {
+            memory_kib,
+            iterations: iterations.unwrap_or(ARGON2_ITERATIONS),
+            parallelism: parallelism.unwrap_or(ARGON2_PARALLELISM),
+            salt: random_salt()
+        },
+        "scrypt" => KdfParams::Scrypt {
+            log_n: log_n.unwrap_or(SCRYPT_LOG_N),
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: random_salt()
+        },
+        "bcrypt" => return Err(CommandError::InvalidInput(
+            "Bcrypt is only supported for opening older databases, choose argon2id or scrypt.".to_string())),
+        _ => return Err(CommandError::InvalidInput(format!("Unknown key derivation function '{}'.", kdf)))
+    };
+    match params.validate() {
+        Ok(()) => Ok(params),
+        Err(why) => Err(CommandError::InvalidInput(format!("{}.", why)))
+    }
+}
+
+/// Suggest parameters making the key derivation take about one second on this machine.
+pub fn benchmark(params: &[&str]) -> CommandResult<()>{
+    if !params.is_empty() {
+        return Err(CommandError::Usage("Invalid arguments.\nUsage: rpass kdf-benchmark"));
+    }
+    println!("Measuring, this takes a few seconds...");
+
+    // Argon2id: keep the default memory unless a single pass is already too slow,
+    // then add iterations until the target time is reached
+    let mut memory_kib = ARGON2_MEMORY_KIB;
+    let mut single_pass = time_derive(&argon2id(memory_kib, 1));
+    while single_pass > TARGET_MILLIS && memory_kib > 8 * 1024 {
+        memory_kib /= 2;
+        single_pass = time_derive(&argon2id(memory_kib, 1));
+    }
+    let iterations = ::std::cmp::max(1, TARGET_MILLIS / ::std::cmp::max(1, single_pass)) as u32;
+    println!("argon2id: --kdf argon2id --memory {} --iterations {} --parallelism {} (~{} ms)",
+             memory_kib / 1024, iterations, ARGON2_PARALLELISM,
+             time_derive(&argon2id(memory_kib, iterations)));
+
+    // Scrypt: every step doubles both memory and time
+    let mut log_n = 14u8;
+    let mut elapsed = time_derive(&scrypt(log_n));
+    while elapsed * 2 <= TARGET_MILLIS && log_n < 20 {
+        log_n += 1;
+        elapsed = time_derive(&scrypt(log_n));
+    }
+    println!("scrypt:   --kdf scrypt --log-n {} (~{} ms)", log_n, elapsed);
+    Ok(())
+}
+
+fn argon2id(memory_kib: u32, iterations: u32) -> KdfParams {
+    KdfParams::Argon2id {
+        memory_kib,
+        iterations,
+        parallelism: ARGON2_PARALLELISM,
+        salt: random_salt()
+    }
+}
+
+fn scrypt(log_n: u8) -> KdfParams {
+    KdfParams::Scrypt { log_n, r: SCRYPT_R, p: SCRYPT_P, salt: random_salt() }
+}
+
+// Duration of a single key derivation in milliseconds
+fn time_derive(params: &KdfParams) -> u64 {
+    let start = Instant::now();
+    let _ = params.derive(BENCHMARK_PASSWORD);
+    millis(start.elapsed())
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_millis() as u64
+}
+
+// Parsed into the type of the parameter, so that values out of its range are refused
+fn parse_number<T: FromStr>(option: &str, value: Option<&&str>) -> CommandResult<T>{
+    let value = match value {
+        Some(value) => *value,
+        None => return Err(CommandError::Usage(USAGE))
+    };
+    match value.parse() {
+        Ok(number) => Ok(number),
+        Err(_) => Err(CommandError::InvalidInput(format!("Invalid value '{}' of {}.", value, option)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use db::kdf::KdfParams;
+    use commands::error::CommandError;
+    use super::parse_kdf_options;
+
+    #[test]
+    fn test_out_of_range() {
+        match parse_kdf_options(&["--kdf", "scrypt", "--log-n", "15"]).unwrap() {
+            KdfParams::Scrypt { log_n, .. } => assert_eq!(log_n, 15),
+            other => panic!("unexpected {:?}", other)
+        }
+        // 271 would be 15 if truncated to u8
+        for params in &[&["--kdf", "scrypt", "--log-n", "271"][..], &["--memory", "4194304"][..],
+                        &["--iterations", "-1"][..], &["--kdf", "bcrypt"][..]] {
+            match parse_kdf_options(params) {
+                Err(CommandError::InvalidInput(_)) => (),
+                other => panic!("{:?} gave {:?}", params, other)
+            }
+        }
+    }
+}
@@ -1,11 +1,57 @@
+use db::{Database, DatabaseInFile, Entry};
+use db::group;
+use commands::error::{CommandError, CommandResult};
+use commands::format::{self, Format, OutputOptions};
 
-use db::{DatabaseInFile, Entry};
+static USAGE: &str = "Invalid arguments.
+Usage: rpass list [<group>] [--tree] [--format json|table|tsv]";
 
-pub fn call(file_db: &Box<DatabaseInFile>){
-	// Print table header
-	Entry::print_short_info_desc();
-	// Print table rows
-	for entry in file_db.db.entries.iter(){
-		entry.print_short_info();
+/// List entries of the group and the groups inside it, all entries by default.
+pub fn call(file_db: &DatabaseInFile, params: &[&str]) -> CommandResult<()>{
+	let (options, rest) = OutputOptions::from_params(params)?;
+	let tree = rest.contains(&"--tree");
+	let rest: Vec<&str> = rest.into_iter().filter(|param| *param != "--tree").collect();
+	let path = match rest.len() {
+		0 => String::new(),
+		1 => match group::normalize(rest[0]) {
+			Ok(path) => path,
+			Err(why) => return Err(CommandError::InvalidInput(why))
+		},
+		_ => return Err(CommandError::Usage(USAGE))
+	};
+	if !file_db.db.group_exists(&path) {
+		return Err(CommandError::InvalidInput(format!("There is no group '{}'.", path)));
+	}
+
+	if tree {
+		if options.format != Format::Table {
+			return Err(CommandError::InvalidInput("The tree view can't be combined with --format.".to_string()));
+		}
+		print_tree(&file_db.db, &path, 0);
+		return Ok(());
+	}
+
+	let entries: Vec<&Entry> = file_db.db.entries.iter()
+		.filter(|entry| group::contains(&path, &entry.group))
+		.collect();
+	format::print_entries(&entries, &options);
+	Ok(())
+}
+
+// Entries of the group first, then its subgroups, everything sorted by name
+fn print_tree(db: &Database, path: &str, depth: usize) {
+	let indent: String = "    ".repeat(depth);
+	let mut entries: Vec<&Entry> = db.entries.iter().filter(|entry| entry.group == path).collect();
+	entries.sort_by(|a, b| a.title.cmp(&b.title));
+	for entry in entries {
+		println!("{}{} ({})", indent, entry.title, entry.username());
+	}
+
+	let children: Vec<String> = db.all_groups().into_iter()
+		.filter(|other| group::parent(other) == Some(path))
+		.collect();
+	for child in children.iter() {
+		println!("{}{}/", indent, group::name(child));
+		print_tree(db, child, depth + 1);
 	}
 }
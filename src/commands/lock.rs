@@ -0,0 +1,29 @@
+use clipboard;
+use db::{DatabaseInFile, DbError};
+use commands::error::{CommandError, CommandResult};
+use commands::input::PasswordSource;
+use commands::open::MAX_PASS_ATTEMPTS;
+
+/// Wipe the decrypted database from memory, clear copied passwords and wait
+/// for the master password. An error means the database stayed locked and the
+/// session has to end.
+pub fn call(file_db: &mut Box<DatabaseInFile>, message: &str) -> CommandResult<()> {
+    file_db.forget_secrets();
+    clipboard::clear_pending();
+    println!("{}", message);
+
+    // Always asked at the terminal, whoever unlocks has to be sitting at it
+    let mut attempts_left = MAX_PASS_ATTEMPTS;
+    loop {
+        let password = PasswordSource::Prompt.read()?;
+        attempts_left -= 1;
+
+        match file_db.unlock(&password) {
+            Ok(()) => return Ok(()),
+            Err(DbError::WrongPassword) if attempts_left > 0 => {
+                println!("Wrong master password (or the file is corrupted), try again.");
+            },
+            Err(why) => return Err(CommandError::Db(why))
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use db::DatabaseInFile;
+use db::group;
+use commands;
+use commands::error::{CommandError, CommandResult};
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass mkdir <group>";
+
+/// Create an empty group, groups above it are created as well.
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    if params.len() != 1 {
+        return Err(CommandError::Usage(USAGE));
+    }
+    let path = match group::normalize(params[0]) {
+        Ok(ref path) if path.is_empty() => return Err(CommandError::Usage(USAGE)),
+        Ok(path) => path,
+        Err(why) => return Err(CommandError::InvalidInput(why))
+    };
+    if file_db.db.group_exists(&path) {
+        return Err(CommandError::InvalidInput(format!("Group '{}' already exists.", path)));
+    }
+
+    file_db.db.groups.insert(path.clone());
+    commands::save(file_db)?;
+    println!("Group '{}' was created.", path);
+    Ok(())
+}
@@ -1,6 +1,7 @@
 #[allow(dead_code)]
 pub mod create;
 pub mod open;
+pub mod lock;
 
 pub mod list;
 pub mod new;
@@ -8,3 +9,69 @@ pub mod show;
 pub mod copy;
 pub mod edit;
 pub mod remove;
+pub mod passwd;
+pub mod backups;
+pub mod restore;
+pub mod settings;
+pub mod stale;
+pub mod history;
+pub mod restore_password;
+pub mod trash;
+pub mod mkdir;
+pub mod mv;
+pub mod rmdir;
+pub mod search;
+pub mod generate;
+pub mod passphrase;
+pub mod kdf;
+pub mod agent;
+
+pub mod complete;
+pub mod error;
+pub mod format;
+pub mod input;
+
+use std::io::{self, Write};
+use db::{DatabaseInFile, SaveReport};
+use commands::error::CommandResult;
+
+/// Save the database and tell the user about changes merged from the file.
+pub fn save(file_db: &mut Box<DatabaseInFile>) -> CommandResult<()> {
+    let report = file_db.save()?;
+    print_report(&mut io::stdout(), &report);
+    Ok(())
+}
+
+/// Remember that the entry was used. This is only bookkeeping, so it's skipped
+/// for a read-only database, the file is rewritten at most once per
+/// `LAST_USED_PRECISION` and a failure doesn't fail the command. Messages go
+/// to stderr, not to mix with the output of `show --field` read by scripts.
+/// The agent only reads the files, uses through it aren't recorded.
+pub fn record_use(file_db: &mut Box<DatabaseInFile>, entry_id: &str) {
+    if file_db.is_read_only() {
+        return;
+    }
+    let changed = match file_db.db.get_mut(entry_id) {
+        Some(entry) => entry.mark_used(),
+        None => false
+    };
+    if !changed {
+        return;
+    }
+    match file_db.save_without_backup() {
+        Ok(report) => print_report(&mut io::stderr(), &report),
+        Err(why) => { let _ = writeln!(io::stderr(), "Could not record the use of the entry: {}", why); }
+    }
+}
+
+fn print_report(out: &mut dyn Write, report: &SaveReport) {
+    if report.merged {
+        let _ = writeln!(out, "The file was changed by another process, the changes were merged.");
+    }
+    if !report.conflicts.is_empty() {
+        let _ = writeln!(out, "Entries changed on both sides were kept in both versions, resolve them by editing:");
+        for title in report.conflicts.iter() {
+            let _ = writeln!(out, "    {}", title);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use db::DatabaseInFile;
+use db::group;
+use commands;
+use commands::error::{CommandError, CommandResult};
+use commands::input::choose_entry;
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass mv <entry> <group> | mv <group>/ <group>";
+
+/// Move an entry to another group, or move a group with everything inside.
+/// A source ending with '/' is always a group, otherwise entries take precedence.
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    if params.len() != 2 {
+        return Err(CommandError::Usage(USAGE));
+    }
+    let target = normalize(params[1])?;
+
+    let is_group = params[0].ends_with(group::SEPARATOR) || file_db.db.find(params[0]).is_empty();
+    if !is_group {
+        let entry_id = choose_entry(&file_db.db, params[0])?;
+        let path = {
+            let entry = file_db.db.get_mut(&entry_id).unwrap();
+            entry.group = target;
+            entry.mark_modified();
+            entry.path()
+        };
+        commands::save(file_db)?;
+        println!("Entry was moved to '{}'.", path);
+        return Ok(());
+    }
+
+    let source = normalize(params[0])?;
+    if source.is_empty() || !file_db.db.group_exists(&source) {
+        return Err(CommandError::EntryNotFound(params[0].to_string()));
+    }
+    // Like mv(1), moving into an existing group keeps the name
+    let target = if file_db.db.group_exists(&target) {
+        group::join(&target, group::name(&source))
+    } else {
+        target
+    };
+    if group::contains(&source, &target) {
+        return Err(CommandError::InvalidInput(format!("Group '{}' can't be moved into itself.", source)));
+    }
+    if file_db.db.group_exists(&target) {
+        return Err(CommandError::InvalidInput(format!("Group '{}' already exists.", target)));
+    }
+
+    file_db.db.move_group(&source, &target);
+    commands::save(file_db)?;
+    println!("Group '{}' was moved to '{}'.", source, target);
+    Ok(())
+}
+
+fn normalize(path: &str) -> CommandResult<String> {
+    group::normalize(path).map_err(CommandError::InvalidInput)
+}
@@ -1,62 +1,81 @@
-use rpassword::read_password;
+use readline::read_password;
 use db::{DatabaseInFile, Entry};
+use db::group;
+use commands;
+use secstr::SecStr;
+use generate::{self, PasswordPolicy};
+use commands::error::{CommandError, CommandResult};
+use commands::generate::parse_generate_option;
+use commands::input::{read_line, read_lines, parse_tags, read_custom_field, confirm};
+use db::entry::is_reserved_field;
 use std::io;
 use std::io::Write;
-use std::io::{Error, ErrorKind};
-
-pub fn call(file_db: &mut Box<DatabaseInFile>){
-
-	match read_inputs_to_entry() {
-		Ok(entry) => {
-			let title = entry.title.clone();
-			file_db.db.add(entry);
-			let res = file_db.save();
-			match res {
-				Err(why) => println!("Error while adding new entry, reason: {}.", why),
-				_ => println!("New entry '{}' added.", title)
-			}
-		},
-		Err(_) => {
-			// TODO log problem
-		}
-	};
+
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+	let policy = parse_generate_option(params)?;
+	let entry = read_inputs_to_entry(policy)?;
+	let title = entry.path();
+	if file_db.db.has_title(&entry.group, &entry.title) &&
+		!confirm(&format!("Entry '{}' already exists, add another one?", title))? {
+		println!("Nothing added.");
+		return Ok(());
+	}
+	file_db.db.add(entry);
+	commands::save(file_db)?;
+	println!("New entry '{}' added.", title);
+	Ok(())
 }
 
-fn read_inputs_to_entry() -> Result<Entry, io::Error>{
-	print!("Title: ");
-	try!(io::stdout().flush());
-	let mut input_title = String::new();
-	try!(io::stdin().read_line(&mut input_title));
+// With a policy given the password is generated, otherwise it's typed
+// and an empty input means generating it with the default policy.
+fn read_inputs_to_entry(policy: Option<PasswordPolicy>) -> CommandResult<Entry>{
+	let input_title = read_line("Title: ")?;
 	if input_title.is_empty(){
-		println!("Title cannot be empty.");
-		return Err(Error::new(ErrorKind::Other, "Empty title"));
+		return Err(CommandError::InvalidInput("Title cannot be empty.".to_string()));
 	}
 
-	print!("Username: ");
-	try!(io::stdout().flush());
-	let mut input_username = String::new();
-	try!(io::stdin().read_line(&mut input_username));
+	let input_group = match group::normalize(&read_line("Group (optional): ")?) {
+		Ok(path) => path,
+		Err(why) => return Err(CommandError::InvalidInput(why))
+	};
+
+	let input_username = read_line("Username: ")?;
 	if input_username.is_empty(){
-		println!("Username cannot be empty.");
-		return Err(Error::new(ErrorKind::Other, "Empty username"));
+		return Err(CommandError::InvalidInput("Username cannot be empty.".to_string()));
 	}
 
-	print!("Password: ");
-	try!(io::stdout().flush());
-	let input_password = try!(read_password());
-	if input_password.is_empty(){
-		println!("Password cannot be empty.");
-		return Err(Error::new(ErrorKind::Other, "Empty password."));
-	}
+	let password = match policy {
+		Some(policy) => generate::generate(&policy)?,
+		None => {
+			print!("Password (leave empty to generate one): ");
+			io::stdout().flush()?;
+			let input_password = read_password()?;
+			if input_password.is_empty(){
+				generate::generate(&PasswordPolicy::new())?
+			} else {
+				SecStr::new(input_password)
+			}
+		}
+	};
+
+	let mut entry = Entry::with_password(input_title, input_username, password);
+	entry.group = input_group;
+	entry.url = read_line("URL (optional): ")?;
+	entry.notes = read_lines("Notes (optional, finish with an empty line): ")?;
+	entry.tags = parse_tags(&read_line("Tags (optional, separated by commas): ")?);
 
-	print!("URL (optional): ");
-	try!(io::stdout().flush());
-	let mut input_url = String::new();
-	try!(io::stdin().read_line(&mut input_url));
+	loop {
+		let name = read_line("Custom field name (Enter to finish): ")?;
+		if name.is_empty() {
+			break;
+		}
+		if is_reserved_field(&name) || entry.field(&name).is_some() {
+			println!("Field '{}' already exists.", name);
+			continue;
+		}
+		let secret = confirm("Is the value secret?")?;
+		entry.set_field(read_custom_field(&name, secret)?);
+	}
 
-	return Ok(Entry::new(
-			input_title.trim(),
-			input_username.trim(),
-			input_password.trim()
-		));
+	Ok(entry)
 }
@@ -1,39 +1,57 @@
-use db::{Database, DatabaseInFile};
+use db::{DatabaseInFile, DbError};
+use db::lock::{self, LockStatus};
+use commands::error::{CommandError, CommandResult};
+use commands::input::PasswordSource;
+use std::io::{self, Write};
 use std::path::Path;
-use rpassword::read_password;
 
-static USAGE: &'static str = "Usage: rpass open <filename>";
+static USAGE: &str = "Invalid arguments.
+Usage: rpass open <filename>";
 
-fn usage(){
-	println!("{}", USAGE);
-}
+pub const MAX_PASS_ATTEMPTS: usize = 3;
 
-pub fn call(params: &[String]) -> Option<Box<DatabaseInFile>>{
-	if params.len() == 0 {
-		usage();
-		return None;
+/// Lock and decrypt the database. Notices go to stderr, not to mix with the
+/// output of one-shot commands read by scripts.
+pub fn call(params: &[String], source: &PasswordSource) -> CommandResult<Box<DatabaseInFile>>{
+	if params.is_empty() {
+		return Err(CommandError::Usage(USAGE));
 	}
 
 	let db_path = Path::new(&params[0]);
 	if !db_path.exists(){
-		println!("No such file exists.");
-		return None;
+		return Err(CommandError::FileNotFound(params[0].clone()));
 	}
 
-	println!("Please enter master password:");
-	let password = read_password().unwrap();
-
-	let res = Database::open_from_file(&db_path, &password);
-	match res {
-		Ok(database) => Some(Box::new(
-			DatabaseInFile{
-				db: database,
-				filepath: params[0].clone()
+	// Lock before reading, so nobody can change the file after we've read it
+	let lock = match lock::acquire(db_path)? {
+		LockStatus::Acquired(lock) => {
+			if let Some(ref owner) = lock.stale_owner {
+				let _ = writeln!(io::stderr(), "Removed a stale lock left by process {}.", owner);
 			}
-		)),
-		Err(why) => {
-			println!("Error opening file, reason: {}", why);
+			Some(lock)
+		},
+		LockStatus::HeldBy(owner) => {
+			let _ = writeln!(io::stderr(), "Database is locked by process {}, opening read-only.", owner);
 			None
 		}
+	};
+
+	// Only a human at the terminal gets another chance
+	let mut attempts_left = match *source {
+		PasswordSource::Prompt => MAX_PASS_ATTEMPTS,
+		_ => 1
+	};
+
+	loop {
+		let password = source.read()?;
+		attempts_left -= 1;
+
+		match DatabaseInFile::open(params[0].clone(), &password) {
+			Ok(file_db) => return Ok(Box::new(file_db.with_lock(lock))),
+			Err(DbError::WrongPassword) if attempts_left > 0 => {
+				let _ = writeln!(io::stderr(), "Wrong master password (or the file is corrupted), try again.");
+			},
+			Err(why) => return Err(CommandError::Db(why))
+		}
 	}
 }
@@ -0,0 +1,40 @@
+use std::path::Path;
+use passphrase::{self, PassphrasePolicy, Wordlist};
+use commands::error::{CommandError, CommandResult};
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass passphrase [--words <n>] [--separator <s>] [--capitalize] [--digit] [--wordlist <file>]
+    --words is at most 64, the default wordlist is the BIP-39 English list (2048 words, 11 bits each)";
+
+pub fn call(params: &[&str]) -> CommandResult<()>{
+    let mut policy = PassphrasePolicy::new();
+    let mut wordlist = None;
+    let mut iter = params.iter();
+    while let Some(param) = iter.next() {
+        match *param {
+            "--words" => policy.words = match iter.next().and_then(|value| value.parse().ok()) {
+                Some(words) => words,
+                None => return Err(CommandError::Usage(USAGE))
+            },
+            "--separator" => policy.separator = match iter.next() {
+                Some(separator) => separator.to_string(),
+                None => return Err(CommandError::Usage(USAGE))
+            },
+            "--capitalize" => policy.capitalize = true,
+            "--digit" => policy.inject_digit = true,
+            "--wordlist" => wordlist = match iter.next() {
+                Some(path) => Some(Wordlist::from_file(Path::new(path))?),
+                None => return Err(CommandError::Usage(USAGE))
+            },
+            _ => return Err(CommandError::Usage(USAGE))
+        }
+    }
+
+    let wordlist = wordlist.unwrap_or_else(Wordlist::embedded);
+    let mut generated = passphrase::generate(&policy, &wordlist)?;
+    generated.secret.unlock();
+    println!("{}", generated.secret.string);
+    generated.secret.delete();
+    println!("Entropy: {:.1} bits ({} words from a list of {}).", generated.entropy, policy.words, wordlist.len());
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use readline::read_password;
+use db::{Database, DatabaseInFile, DbError};
+use db::atomic;
+use db::kdf::KDF_BCRYPT;
+use commands::create::get_pass;
+use commands::error::{CommandError, CommandResult};
+use commands::input::confirm;
+use commands::kdf::parse_kdf_options;
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass passwd [kdf options]";
+
+/// Change the master password. Without options the KDF parameters stay the same,
+/// only the salt is renewed, except that bcrypt of older databases is replaced
+/// with the default.
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    if file_db.is_read_only() {
+        return Err(CommandError::Db(DbError::ReadOnly));
+    }
+    // Re-encrypting would silently drop changes made by somebody else
+    if file_db.is_modified_externally()? {
+        return Err(CommandError::Db(DbError::ConcurrentModification));
+    }
+    let kdf = if params.is_empty() && file_db.db.kdf().id() != KDF_BCRYPT {
+        file_db.db.kdf().with_new_salt()
+    } else {
+        match parse_kdf_options(params) {
+            Ok(kdf) => kdf,
+            Err(CommandError::Usage(_)) => return Err(CommandError::Usage(USAGE)),
+            Err(why) => return Err(why)
+        }
+    };
+
+    println!("Please enter the current master password:");
+    let current = read_password()?;
+    if !file_db.db.check_password(&current) {
+        return Err(CommandError::Db(DbError::WrongPassword));
+    }
+    let new = get_pass()?;
+
+    let old_kdf = file_db.db.kdf().clone();
+    println!("Deriving the key with {}...", kdf.describe());
+    file_db.db.change_password(&new, kdf)?;
+
+    if let Err(why) = rewrite(file_db, &new) {
+        // Keep the session usable with the password the file still has,
+        // it was checked above so the key derives again
+        let _ = file_db.db.change_password(&current, old_kdf);
+        return Err(why);
+    }
+    file_db.reload_base()?;
+    println!("Master password was successfully changed.");
+    remove_old_backups(file_db)
+}
+
+// The backups are still encrypted with the old password, which may be the very reason for the change
+fn remove_old_backups(file_db: &DatabaseInFile) -> CommandResult<()> {
+    let backups = file_db.backups();
+    if backups.is_empty() {
+        return Ok(());
+    }
+    let question = format!("The old master password still opens {} backups, delete them?", backups.len());
+    if !confirm(&question)? {
+        println!("Warning: the backups were kept, anybody knowing the old master password can read them.");
+        return Ok(());
+    }
+    for backup in backups {
+        fs::remove_file(&backup.path)?;
+    }
+    println!("Backups were deleted.");
+    Ok(())
+}
+
+// The new file is written next to the old one and opened again before it replaces it.
+// Until then a copy of the old file is kept as a backup. The rename is the commit point,
+// an error is only returned if the file on disk still has the old password.
+fn rewrite(file_db: &DatabaseInFile, password: &str) -> CommandResult<()>{
+    let path = Path::new(&file_db.filepath);
+    let backup_path = format!("{}.bak", file_db.filepath);
+    let new_path = format!("{}.new", file_db.filepath);
+
+    fs::copy(path, &backup_path)?;
+    file_db.db.save_to_file(Path::new(&new_path))?;
+
+    let verified = match Database::open_from_file(Path::new(&new_path), password) {
+        Ok(reopened) => reopened.entries.len() == file_db.db.entries.len(),
+        Err(_) => false
+    };
+    if !verified {
+        let _ = fs::remove_file(&new_path);
+        let _ = fs::remove_file(&backup_path);
+        return Err(CommandError::InvalidInput(
+            "Verification of the re-encrypted database failed, the master password was not changed.".to_string()));
+    }
+
+    fs::rename(&new_path, path)?;
+    // The file has the new password from here on, so failures are only reported
+    // and must not make the caller roll back to the old key
+    if let Err(why) = atomic::sync_dir(path) {
+        let _ = writeln!(io::stderr(), "Warning: the new database may not be on disk yet: {}", why);
+    }
+    if let Err(why) = fs::remove_file(&backup_path) {
+        let _ = writeln!(io::stderr(), "Warning: {} encrypted with the old master password could not be removed: {}",
+                         backup_path, why);
+    }
+    Ok(())
+}
@@ -1,22 +1,32 @@
 use db::DatabaseInFile;
+use commands;
+use commands::error::{CommandError, CommandResult};
+use commands::input::{choose_entry, choose_exact_entry, confirm};
 
-static USAGE: &'static str = "Invalid arguments.
-Usage: rpass remove <entry>";
+static USAGE: &str = "Invalid arguments.
+Usage: rpass remove <entry> [--yes]";
 
-fn usage(){
-    println!("{}", USAGE);
-}
-
-pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]){
-    if params.len() == 0 {
-        usage();
-        return;
-    }
+/// Move the entry to the trash, `--yes` skips the confirmation (for scripts).
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    let confirmed = match params.len() {
+        1 => false,
+        2 if params[1] == "--yes" => true,
+        _ => return Err(CommandError::Usage(USAGE))
+    };
 
-    let entry_id = params[0];
-    if file_db.db.remove(entry_id){
-        println!("Entry '{}' was successfully removed.", entry_id);
+    let entry_id = if confirmed {
+        choose_exact_entry(&file_db.db, params[0])?
     } else {
-        println!("No entry named '{}' was found.", entry_id);
+        choose_entry(&file_db.db, params[0])?
+    };
+    let title = file_db.db.get(&entry_id).unwrap().title.clone();
+    if !confirmed && !confirm(&format!("Move entry '{}' to the trash?", title))? {
+        println!("Nothing removed.");
+        return Ok(());
     }
+
+    file_db.db.remove(&entry_id);
+    commands::save(file_db)?;
+    println!("Entry '{}' was moved to the trash, 'trash restore' brings it back.", title);
+    Ok(())
 }
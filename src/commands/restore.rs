@@ -0,0 +1,34 @@
+use db::DatabaseInFile;
+use commands;
+use commands::error::{CommandError, CommandResult};
+use commands::input::confirm;
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass restore <backup number>";
+
+/// Replace the database with one of its backups. The current state becomes
+/// the newest backup, so the restore can be undone the same way.
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    let number: usize = match params.first().and_then(|value| value.parse().ok()) {
+        Some(number) if params.len() == 1 => number,
+        _ => return Err(CommandError::Usage(USAGE))
+    };
+
+    let backup = match file_db.backups().into_iter().find(|backup| backup.number == number) {
+        Some(backup) => backup,
+        None => return Err(CommandError::InvalidInput(format!("There is no backup number {}.", number)))
+    };
+    let restored = file_db.db.open_with_same_key(&backup.path)?;
+
+    let question = format!("Replace the database ({} entries) with backup {} ({} entries)?",
+                           file_db.db.entries.len(), number, restored.entries.len());
+    if !confirm(&question)? {
+        println!("Nothing restored.");
+        return Ok(());
+    }
+
+    file_db.db = restored;
+    commands::save(file_db)?;
+    println!("Backup {} was restored.", number);
+    Ok(())
+}
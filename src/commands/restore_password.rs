@@ -0,0 +1,41 @@
+use db::DatabaseInFile;
+use db::entry::format_time;
+use commands;
+use commands::error::{CommandError, CommandResult};
+use commands::input::{choose_entry, confirm};
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass restore-password <entry> <n>";
+
+/// Make a previous password of an entry current again, see `history`.
+/// The current password goes to the history, so this can be undone.
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    let number: usize = match params.get(1).and_then(|value| value.parse().ok()) {
+        Some(number) if params.len() == 2 => number,
+        _ => return Err(CommandError::Usage(USAGE))
+    };
+
+    let entry_id = choose_entry(&file_db.db, params[0])?;
+    let history_length = file_db.db.settings.history_length;
+    let title = {
+        let entry = file_db.db.get_mut(&entry_id).unwrap();
+        let replaced = match entry.history.get(number.wrapping_sub(1)) {
+            Some(previous) => previous.replaced,
+            None => return Err(CommandError::InvalidInput(
+                format!("Entry '{}' has no previous password number {}.", entry.title, number)))
+        };
+        let question = format!("Restore the password of '{}' replaced at {}?",
+                               entry.title, format_time(replaced, "unknown time"));
+        if !confirm(&question)? {
+            println!("Password was not restored.");
+            return Ok(());
+        }
+        entry.restore_password(number, history_length);
+        entry.mark_modified();
+        entry.title.clone()
+    };
+
+    commands::save(file_db)?;
+    println!("Previous password of '{}' was restored.", title);
+    Ok(())
+}
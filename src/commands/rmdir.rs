@@ -0,0 +1,31 @@
+use db::DatabaseInFile;
+use db::group;
+use commands;
+use commands::error::{CommandError, CommandResult};
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass rmdir <group>";
+
+/// Remove an empty group.
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    if params.len() != 1 {
+        return Err(CommandError::Usage(USAGE));
+    }
+    let path = match group::normalize(params[0]) {
+        Ok(ref path) if path.is_empty() => return Err(CommandError::Usage(USAGE)),
+        Ok(path) => path,
+        Err(why) => return Err(CommandError::InvalidInput(why))
+    };
+    if !file_db.db.group_exists(&path) {
+        return Err(CommandError::InvalidInput(format!("There is no group '{}'.", path)));
+    }
+    if !file_db.db.is_group_empty(&path) {
+        return Err(CommandError::InvalidInput(
+            format!("Group '{}' is not empty, move or remove its entries and groups first.", path)));
+    }
+
+    file_db.db.groups.remove(&path);
+    commands::save(file_db)?;
+    println!("Group '{}' was removed.", path);
+    Ok(())
+}
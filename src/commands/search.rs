@@ -0,0 +1,27 @@
+use db::{DatabaseInFile, Entry};
+use search::{self, Query};
+use commands::error::{CommandError, CommandResult};
+use commands::format::{self, OutputOptions};
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass search <term>... [--regex] [--include-secrets] [--format json|table|tsv]
+Terms can be scoped to a field: title:, user:, url:, tag:, notes:, group:, password:";
+
+/// List entries matching all the terms, the most relevant first.
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    let (options, rest) = OutputOptions::from_params(params)?;
+    let regex = rest.contains(&"--regex");
+    let include_secrets = rest.contains(&"--include-secrets");
+    let terms: Vec<&str> = rest.into_iter()
+        .filter(|param| *param != "--regex" && *param != "--include-secrets")
+        .collect();
+    if terms.is_empty() {
+        return Err(CommandError::Usage(USAGE));
+    }
+
+    let query = Query::parse(&terms, regex, include_secrets)?;
+    let found = search::search(&query, &mut file_db.db.entries);
+    let entries: Vec<&Entry> = found.iter().map(|index| &file_db.db.entries[*index]).collect();
+    format::print_entries(&entries, &options);
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use db::DatabaseInFile;
+use commands;
+use commands::error::{CommandError, CommandResult};
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass settings [<name> <value>]";
+
+/// Show all settings of the vault, or change one of them.
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    match params.len() {
+        0 => {
+            for (name, value) in file_db.db.settings.values() {
+                println!("{0: <20} {1}", name, value);
+            }
+            Ok(())
+        },
+        2 => {
+            if let Err(why) = file_db.db.settings.set(params[0], params[1]) {
+                return Err(CommandError::InvalidInput(why));
+            }
+            // A shorter history applies to the passwords kept so far too
+            let history_length = file_db.db.settings.history_length;
+            for entry in file_db.db.entries.iter_mut() {
+                entry.trim_history(history_length);
+            }
+            commands::save(file_db)?;
+            println!("Setting '{}' was changed.", params[0]);
+            Ok(())
+        },
+        _ => Err(CommandError::Usage(USAGE))
+    }
+}
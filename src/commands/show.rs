@@ -1,17 +1,35 @@
 use db::DatabaseInFile;
+use commands;
+use commands::error::{CommandError, CommandResult};
+use commands::format::{self, OutputOptions};
+use commands::input::{choose_entry, choose_exact_entry};
 
-static USAGE: &'static str = "Invalid arguments.
-Usage: rpass show <entry>";
+static USAGE: &str = "Invalid arguments.
+Usage: rpass show <entry> [--field <name>] [--format json|table|tsv] [--reveal]";
 
-pub fn call(file_db: &Box<DatabaseInFile>, params: &[&str]){
-    if params.len() == 0 {
-        println!("{}", USAGE);
-        return;
-    }
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    let (options, params) = OutputOptions::from_params(params)?;
+    let field = match params.len() {
+        1 => None,
+        3 if params[1] == "--field" => Some(params[2]),
+        _ => return Err(CommandError::Usage(USAGE))
+    };
 
-    let entry_title = params[0];
-    match file_db.db.get(entry_title) {
-        Some(entry) => entry.print_full_info(),
-        None => println!("No entry named '{}' was found.", entry_title)
+    // A script reading the field must not get the value of another entry
+    let entry_id = match field {
+        Some(_) => choose_exact_entry(&file_db.db, params[0])?,
+        None => choose_entry(&file_db.db, params[0])?
+    };
+    {
+        let entry = file_db.db.get_mut(&entry_id).unwrap();
+        match field {
+            // Single raw value, meant for scripts
+            Some(field) => if !entry.print_field(field) {
+                return Err(CommandError::InvalidInput(format!("Entry has no field '{}'.", field)));
+            },
+            None => format::print_entry(entry, &options)
+        }
     }
+    commands::record_use(file_db, &entry_id);
+    Ok(())
 }
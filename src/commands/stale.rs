@@ -0,0 +1,47 @@
+use db::{DatabaseInFile, Entry};
+use db::entry::format_time;
+use commands::error::{CommandError, CommandResult};
+use commands::format::{self, Format, OutputOptions};
+use timestamp;
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass stale [--older-than <age>] [--format json|table|tsv]";
+
+/// Passwords older than this are stale unless --older-than says otherwise.
+const DEFAULT_MAX_AGE: &str = "180d";
+
+/// List entries whose password hasn't been changed within the given time,
+/// oldest first. Entries from older files with unknown age are listed too.
+pub fn call(file_db: &DatabaseInFile, params: &[&str]) -> CommandResult<()>{
+    let (options, rest) = OutputOptions::from_params(params)?;
+    let max_age = match rest.len() {
+        0 => DEFAULT_MAX_AGE,
+        2 if rest[0] == "--older-than" => rest[1],
+        _ => return Err(CommandError::Usage(USAGE))
+    };
+    let seconds = match timestamp::parse_duration(max_age) {
+        Some(seconds) => seconds,
+        None => return Err(CommandError::InvalidInput(
+            format!("Invalid age '{}', use a number with a unit h, d, w, m or y (e.g. 180d).", max_age)))
+    };
+
+    let cutoff = timestamp::now().saturating_sub(seconds);
+    let mut stale: Vec<&Entry> = file_db.db.entries.iter()
+        .filter(|entry| entry.password_changed < cutoff)
+        .collect();
+    stale.sort_by_key(|entry| entry.password_changed);
+
+    if stale.is_empty() && options.format == Format::Table {
+        println!("No password is older than {}.", max_age);
+        return Ok(());
+    }
+    let rows: Vec<Vec<(&'static str, String)>> = stale.iter()
+        .map(|entry| {
+            let mut row = entry.summary_fields();
+            row.push(("password_changed", format_time(entry.password_changed, "unknown")));
+            row
+        })
+        .collect();
+    format::print_rows(&rows, &options);
+    Ok(())
+}
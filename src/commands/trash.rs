@@ -0,0 +1,60 @@
+use db::DatabaseInFile;
+use db::entry::format_time;
+use commands;
+use commands::error::{CommandError, CommandResult};
+use commands::format::{self, OutputOptions};
+use commands::input::{choose_trashed_entry, confirm};
+
+static USAGE: &str = "Invalid arguments.
+Usage: rpass trash list [--format json|table|tsv] | trash restore <entry> | trash empty";
+
+/// Work with removed entries, see `remove`.
+pub fn call(file_db: &mut Box<DatabaseInFile>, params: &[&str]) -> CommandResult<()>{
+    match params.first() {
+        Some(&"list") => list(file_db, &params[1..]),
+        Some(&"restore") if params.len() == 2 => restore(file_db, params[1]),
+        Some(&"empty") if params.len() == 1 => empty(file_db),
+        _ => Err(CommandError::Usage(USAGE))
+    }
+}
+
+fn list(file_db: &DatabaseInFile, params: &[&str]) -> CommandResult<()>{
+    let (options, rest) = OutputOptions::from_params(params)?;
+    if !rest.is_empty() {
+        return Err(CommandError::Usage(USAGE));
+    }
+    let rows: Vec<Vec<(&'static str, String)>> = file_db.db.trash.iter()
+        .map(|trashed| {
+            let mut row = trashed.entry.summary_fields();
+            row.push(("removed", format_time(trashed.removed, "unknown")));
+            row
+        })
+        .collect();
+    format::print_rows(&rows, &options);
+    Ok(())
+}
+
+fn restore(file_db: &mut Box<DatabaseInFile>, query: &str) -> CommandResult<()>{
+    let entry_id = choose_trashed_entry(&file_db.db, query)?;
+    file_db.db.restore_from_trash(&entry_id);
+    let title = file_db.db.get(&entry_id).unwrap().title.clone();
+    commands::save(file_db)?;
+    println!("Entry '{}' was restored from the trash.", title);
+    Ok(())
+}
+
+fn empty(file_db: &mut Box<DatabaseInFile>) -> CommandResult<()>{
+    if file_db.db.trash.is_empty() {
+        println!("The trash is empty.");
+        return Ok(());
+    }
+    let question = format!("Delete {} entries in the trash for good?", file_db.db.trash.len());
+    if !confirm(&question)? {
+        println!("Nothing deleted.");
+        return Ok(());
+    }
+    let count = file_db.db.empty_trash();
+    commands::save(file_db)?;
+    println!("{} entries were deleted.", count);
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//!
+//! Crash-safe replacement of a file.
+//!
+//! The new content goes to a temporary file in the same directory, which is
+//! fsynced and then renamed over the original. Rename within one filesystem
+//! is atomic, so after a crash there is either the old or the new file, never
+//! a truncated one. The directory is fsynced as well to persist the rename.
+//!
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use libc;
+use rand::{Rng, OsRng};
+use db::error::DbResult;
+
+/// Permissions of newly created files, readable by the owner only.
+pub const NEW_FILE_MODE: u32 = 0o600;
+
+/// Attempts to find an unused name for the temporary file.
+const TEMP_ATTEMPTS: usize = 10;
+
+pub fn write_file<F>(path: &Path, write: F) -> DbResult<()>
+    where F: FnOnce(&mut File) -> DbResult<()>
+{
+    let (mut file, tmp_path) = create_temp(path)?;
+    let res = write_temp(path, &tmp_path, &mut file, write)
+        .and_then(|_| fs::rename(&tmp_path, path).map_err(From::from))
+        .and_then(|_| sync_dir(path).map_err(From::from));
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    res
+}
+
+// A random suffix, files left behind by a crash can't block the next save
+fn create_temp(path: &Path) -> io::Result<(File, PathBuf)> {
+    let mut rng = OsRng::new()?;
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut attempts_left = TEMP_ATTEMPTS;
+    loop {
+        let tmp_path = path.with_file_name(format!(".{}.tmp.{:08x}", name, rng.next_u32()));
+        attempts_left -= 1;
+        match OpenOptions::new().write(true).create_new(true).mode(NEW_FILE_MODE).open(&tmp_path) {
+            Ok(file) => return Ok((file, tmp_path)),
+            Err(ref why) if why.kind() == ErrorKind::AlreadyExists && attempts_left > 0 => (),
+            Err(why) => return Err(why)
+        }
+    }
+}
+
+fn write_temp<F>(path: &Path, tmp_path: &Path, file: &mut File, write: F) -> DbResult<()>
+    where F: FnOnce(&mut File) -> DbResult<()>
+{
+    // Keep permissions and ownership of the file being replaced
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(tmp_path, metadata.permissions())?;
+        if metadata.uid() != unsafe { libc::geteuid() } || metadata.gid() != unsafe { libc::getegid() } {
+            // Only root can give files away, for anybody else the file stays ours
+            if unsafe { libc::fchown(file.as_raw_fd(), metadata.uid(), metadata.gid()) } != 0 {
+                let _ = writeln!(io::stderr(), "Warning: could not keep the owner of {}, it will be owned by you: {}",
+                                 path.display(), io::Error::last_os_error());
+            }
+        }
+    }
+
+    write(file)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Fsync the directory of the path, so that a rename or a new file in it persists.
+pub fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    };
+    File::open(dir)?.sync_all()
+}
@@ -0,0 +1,143 @@
+//!
+//! Rotating backups of the vault file.
+//!
+//! Before a save replaces the vault, the previous version is copied to
+//! `<vault>.bak.1` and older generations move one number up, up to the
+//! configured count. Backups are either next to the vault or in a
+//! configured directory.
+//!
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use timestamp::{self, Timestamp};
+use db::atomic;
+
+pub struct Backup {
+    /// Generation, 1 is the most recent.
+    pub number: usize,
+    pub path: PathBuf,
+    pub modified: Timestamp
+}
+
+pub fn backup_path(vault: &Path, dir: Option<&Path>, number: usize) -> PathBuf {
+    let name = format!("{}.bak.{}",
+                       vault.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+                       number);
+    match dir {
+        Some(dir) => dir.join(name),
+        None => vault.with_file_name(name)
+    }
+}
+
+/// Shift the existing backups and copy the current vault file to generation 1.
+/// Generations which wouldn't fit into `count` any more are deleted.
+pub fn rotate(vault: &Path, dir: Option<&Path>, count: usize) -> io::Result<()> {
+    if count == 0 || !vault.exists() {
+        return Ok(());
+    }
+    if let Some(dir) = dir {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Also those left over from a higher count
+    for (number, path) in generations(vault, dir)? {
+        if number >= count {
+            fs::remove_file(&path)?;
+        }
+    }
+    for number in (1..count).rev() {
+        let path = backup_path(vault, dir, number);
+        if path.exists() {
+            fs::rename(&path, backup_path(vault, dir, number + 1))?;
+        }
+    }
+    // fs::copy keeps the permissions of the vault
+    let newest = backup_path(vault, dir, 1);
+    fs::copy(vault, &newest)?;
+    File::open(&newest)?.sync_all()?;
+    atomic::sync_dir(&newest)
+}
+
+/// Numbers and paths of all backup files of the vault, whatever the count.
+fn generations(vault: &Path, dir: Option<&Path>) -> io::Result<Vec<(usize, PathBuf)>> {
+    // The name of generation 0 without the number
+    let template = backup_path(vault, dir, 0);
+    let prefix = template.file_name().unwrap().to_string_lossy().trim_end_matches('0').to_string();
+    let dir = match template.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    };
+
+    let mut found = Vec::new();
+    for item in fs::read_dir(dir)? {
+        let item = item?;
+        let name = item.file_name().to_string_lossy().into_owned();
+        if !name.starts_with(&prefix) {
+            continue;
+        }
+        if let Ok(number) = name[prefix.len()..].parse::<usize>() {
+            found.push((number, item.path()));
+        }
+    }
+    Ok(found)
+}
+
+/// Existing backups, most recent first.
+pub fn list(vault: &Path, dir: Option<&Path>, count: usize) -> Vec<Backup> {
+    (1..count + 1)
+        .map(|number| (number, backup_path(vault, dir, number)))
+        .filter_map(|(number, path)| {
+            let modified = match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                Ok(time) => timestamp::from_system_time(time),
+                Err(_) => return None
+            };
+            Some(Backup { number, path, modified })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::Path;
+    use super::{rotate, list, backup_path};
+
+    fn write(path: &Path, content: &str) {
+        File::create(path).unwrap().write_all(content.as_bytes()).unwrap();
+    }
+
+    fn read(path: &Path) -> String {
+        let mut content = String::new();
+        File::open(path).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn test_rotate() {
+        let dir = env::temp_dir().join(format!("rpass-test-backup-{}", unsafe { ::libc::getpid() }));
+        fs::create_dir_all(&dir).unwrap();
+        let vault = dir.join("vault.db");
+
+        for version in 0..4 {
+            write(&vault, &version.to_string());
+            rotate(&vault, None, 2).unwrap();
+        }
+
+        let backups = list(&vault, None, 2);
+        assert_eq!(backups.len(), 2);
+        assert_eq!(read(&backup_path(&vault, None, 1)), "3");
+        assert_eq!(read(&backup_path(&vault, None, 2)), "2");
+        assert!(!backup_path(&vault, None, 3).exists());
+
+        // Lowering the count deletes the generations above it
+        rotate(&vault, None, 4).unwrap();
+        rotate(&vault, None, 4).unwrap();
+        assert!(backup_path(&vault, None, 3).exists());
+        rotate(&vault, None, 1).unwrap();
+        assert_eq!(list(&vault, None, 4).len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
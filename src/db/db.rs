@@ -1,150 +1,524 @@
 use std::path::Path;
 use std::fs::File;
-use std::io::{Write, Read, self, Error, ErrorKind};
+use std::io::{self, Write, Read};
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+use std::ptr;
 use db::Entry;
-use nacl::secretbox::{SecretKey, SecretMsg};
-use rand::{ Rng, OsRng };
-use crypto::bcrypt::bcrypt;
+use db::entry::legacy_id;
+use db::error::{DbError, DbResult};
+use db::atomic;
+use db::backup::{self, Backup};
+use db::format::{self, RawFile};
+use db::kdf::KdfParams;
+use db::lock::VaultLock;
+use db::merge;
+use db::settings::Settings;
+use db::trash::{self, TrashedEntry};
+use db::group;
+use timestamp;
+use nacl::secretbox::KEY_BYTES;
+use crypto::util::fixed_time_eq;
 use serde_json;
 
-const DB_VERSION: u8 = 1u8;
-
-const SALT_SIZE: usize = 16;
-const PASS_SIZE: usize = 24;
-const BCRYPT_COST: u32 = 10;
+/// Shortest id prefix accepted in place of a title.
+pub const MIN_ID_PREFIX: usize = 4;
 
 pub struct DatabaseInFile {
     pub db: Database,
-    pub filepath: String
+    pub filepath: String,
+    /// Exclusive lock of the file, None if the database was opened read-only.
+    lock: Option<VaultLock>,
+    /// File content as we last read or wrote it, the base for merging changes
+    /// made to the file by somebody else in the meantime.
+    base: Vec<u8>
+}
+
+/// What happened while saving.
+pub struct SaveReport {
+    /// The file was changed by somebody else and the changes were merged.
+    pub merged: bool,
+    /// Titles of entries changed on both sides, see `merge`.
+    pub conflicts: Vec<String>
 }
 
 impl DatabaseInFile {
-    pub fn save(&self) -> io::Result<()>{
-        self.db.save_to_file(Path::new(&self.filepath))
+    /// Open the file read-only, see `with_lock`.
+    pub fn open(filepath: String, password: &str) -> DbResult<DatabaseInFile> {
+        let (db, base) = read_database(&filepath, password)?;
+        Ok(DatabaseInFile { db, filepath, lock: None, base })
     }
-}
 
-pub struct Database {
-    bcrypt_salt: [u8; SALT_SIZE],
-    bcrypt_pass: [u8; PASS_SIZE],
-    pub entries: Vec<Entry>
-}
+    /// Allow saving while holding the lock of the file.
+    pub fn with_lock(mut self, lock: Option<VaultLock>) -> DatabaseInFile {
+        self.lock = lock;
+        self
+    }
 
-impl Database {
-    pub fn empty(password: &str) -> Database {
-        let mut salt = [0u8; SALT_SIZE]; // 16bytes of salt bcrypt
-        let mut bcrypt_output = [0u8; PASS_SIZE]; // output 24 bytes
-        OsRng::new().unwrap().fill_bytes(&mut salt);
+    /// Write a new database to the file.
+    pub fn create(filepath: String, db: Database, lock: VaultLock) -> DbResult<DatabaseInFile> {
+        let mut file_db = DatabaseInFile { db, filepath, lock: Some(lock), base: Vec::new() };
+        file_db.write()?;
+        Ok(file_db)
+    }
 
-        // TODO take only first 72 characters of input
-        bcrypt(BCRYPT_COST, &salt, password.as_bytes(), &mut bcrypt_output);
+    /// Somebody else holds the lock, changes can't be saved.
+    pub fn is_read_only(&self) -> bool {
+        self.lock.is_none()
+    }
 
-        Database {
-            bcrypt_salt: salt,
-            bcrypt_pass: bcrypt_output,
-            entries: Vec::new()
-		}
+    /// The file is different from what we read or wrote last time.
+    pub fn is_modified_externally(&self) -> DbResult<bool> {
+        Ok(read_file(Path::new(&self.filepath))? != self.base)
     }
 
-    pub fn open_from_file(path: &Path, password: &str) -> io::Result<Database> {
-        // let mut file = try!(File::open(Path::new(file_path)));
-        let mut file = try!(File::open(path));
-        Database::open(password, &mut file)
+    /// Take the current file content as the new base, after it was written
+    /// by other means than `save`.
+    pub fn reload_base(&mut self) -> DbResult<()> {
+        self.base = read_file(Path::new(&self.filepath))?;
+        Ok(())
+    }
+
+    /// Save the database, the previous version of the file becomes the newest backup.
+    /// If the file was changed by somebody else since we read it, the changes are merged first.
+    pub fn save(&mut self) -> DbResult<SaveReport>{
+        self.save_with_backup(true)
     }
 
-    pub fn save_to_file(&self, path: &Path) -> io::Result<()> {
-        // Open the file in write-only mode
-        let mut file = try!(File::create(path));
-        self.save(&mut file)
+    /// Save a change not worth a backup, like the time an entry was last used.
+    /// Backups would otherwise be pushed out by merely reading the entries.
+    pub fn save_without_backup(&mut self) -> DbResult<SaveReport>{
+        self.save_with_backup(false)
     }
 
-    pub fn open<T: Read>(password: &str, src: &mut T) -> io::Result<Database> {
-        let mut salt = [0u8; SALT_SIZE]; // 16bytes of salt bcrypt
-        let mut bcrypt_output = [0u8; PASS_SIZE]; // output 24 bytes
-        let mut version_buffer = [0u8; 1];
+    fn save_with_backup(&mut self, backup: bool) -> DbResult<SaveReport>{
+        if self.is_read_only() {
+            return Err(DbError::ReadOnly);
+        }
+
+        let mut report = SaveReport { merged: false, conflicts: Vec::new() };
+        let path = Path::new(&self.filepath).to_path_buf();
+        if path.exists() {
+            let current = read_file(&path)?;
+            if current != self.base {
+                report.conflicts = self.merge_from(&current)?;
+                report.merged = true;
+            }
+        }
+
+        self.db.expire_trash();
+        if backup {
+            backup::rotate(&path, self.backup_dir(), self.db.settings.backup_count)?;
+        }
+        self.write()?;
+        Ok(report)
+    }
 
-        match src.read(&mut version_buffer){
-            Ok(_) => (),
+    /// Read the file again if somebody else changed it, using the key we already
+    /// have, for long-running readers like the agent. Fails if the file is now
+    /// encrypted with another key.
+    pub fn refresh(&mut self) -> DbResult<()> {
+        let current = read_file(Path::new(&self.filepath))?;
+        if current != self.base {
+            let mut db = self.db.decrypt_with_same_key(&current)?;
+            db.expire_trash();
+            self.db = db;
+            self.base = current;
+        }
+        Ok(())
+    }
+
+    /// Wipe the secrets from memory and release the lock of the file.
+    pub fn close(mut self) {
+        self.db.wipe();
+    }
+
+    /// Wipe the secrets from memory but keep the lock of the file, until `unlock`
+    /// decrypts it again. Nothing may be saved in between, the database is empty.
+    pub fn forget_secrets(&mut self) {
+        self.db.wipe();
+    }
+
+    /// Decrypt the file again after `forget_secrets`. Changes made by others
+    /// in the meantime are simply read, there is nothing local to merge.
+    pub fn unlock(&mut self, password: &str) -> DbResult<()> {
+        let (db, base) = read_database(&self.filepath, password)?;
+        self.db = db;
+        self.base = base;
+        Ok(())
+    }
+
+    /// Existing backups of the file, most recent first.
+    pub fn backups(&self) -> Vec<Backup> {
+        backup::list(Path::new(&self.filepath), self.backup_dir(), self.db.settings.backup_count)
+    }
+
+    fn backup_dir(&self) -> Option<&Path> {
+        self.db.settings.backup_dir.as_ref().map(Path::new)
+    }
+
+    fn write(&mut self) -> DbResult<()> {
+        let mut bytes = Vec::new();
+        self.db.save(&mut bytes)?;
+        atomic::write_file(Path::new(&self.filepath), |file| {
+            file.write_all(&bytes)?;
+            Ok(())
+        })?;
+        self.base = bytes;
+        Ok(())
+    }
+
+    // Three-way merge of the entries we opened, ours and the ones in the file now
+    fn merge_from(&mut self, current: &[u8]) -> DbResult<Vec<String>> {
+        // A different key means the master password was changed elsewhere, we can't read it
+        let remote = match self.db.decrypt_with_same_key(current) {
+            Ok(remote) => remote,
+            Err(DbError::WrongPassword) => return Err(DbError::ConcurrentModification),
             Err(why) => return Err(why)
         };
-        if version_buffer[0] != DB_VERSION {
-            return Database::invalid_data_error(format!("Cannot process DB version {}", version_buffer[0]));
+        let base = self.db.decrypt_with_same_key(&self.base)?;
+
+        if self.db.settings == base.settings {
+            self.db.settings = remote.settings;
         }
+        let local = mem::take(&mut self.db.entries);
+        let result = merge::merge(base.entries, local, remote.entries);
+        self.db.entries = result.entries;
+        let local_trash = mem::take(&mut self.db.trash);
+        self.db.trash = merge::merge_trash(base.trash, local_trash, remote.trash, &self.db.entries);
+        let local_groups = mem::take(&mut self.db.groups);
+        self.db.groups = merge::merge_groups(base.groups, local_groups, remote.groups);
+        Ok(result.conflicts)
+    }
+}
 
-        match src.read(&mut salt){
-            Ok(SALT_SIZE) => (),
-            Ok(count) => return Database::invalid_data_error(format!("Bad number of bytes {} read for salt.", count)),
-            Err(why) => return Err(why)
+fn find_entries<'a>(entries: Vec<&'a Entry>, query: &str) -> Vec<&'a Entry> {
+    let by_title: Vec<&Entry> = entries.iter().cloned().filter(|entry| entry.title == query).collect();
+    if !by_title.is_empty() {
+        return by_title;
+    }
+    if let Some(index) = query.rfind(group::SEPARATOR) {
+        // A leading separator addresses the root group
+        let (path, title) = (query[..index].trim_matches(group::SEPARATOR), &query[index + 1..]);
+        let by_path: Vec<&Entry> = entries.iter().cloned()
+            .filter(|entry| entry.group == path && entry.title == title)
+            .collect();
+        if !by_path.is_empty() {
+            return by_path;
+        }
+    }
+    if query.len() < MIN_ID_PREFIX {
+        return Vec::new();
+    }
+    entries.into_iter().filter(|entry| entry.id.starts_with(query)).collect()
+}
+
+// Entries from files written before ids existed
+fn assign_missing_ids(entries: &mut [Entry]) {
+    let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+    for entry in entries.iter_mut() {
+        let occurrence = {
+            let count = seen.entry(entry.title.clone()).or_insert(0);
+            *count += 1;
+            *count - 1
+        };
+        if entry.id.is_empty() {
+            entry.id = legacy_id(&entry.title, occurrence);
         }
+    }
+}
+
+fn read_file(path: &Path) -> DbResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
 
-        // Read the rest
-        let mut buffer = Vec::new();
-        try!(src.read_to_end(&mut buffer));
+// The database and the file content it was decrypted from, the base of merges
+fn read_database(filepath: &str, password: &str) -> DbResult<(Database, Vec<u8>)> {
+    let base = read_file(Path::new(filepath))?;
+    let mut db = Database::open(password, &mut &base[..])?;
+    db.expire_trash();
+    Ok((db, base))
+}
 
-        // Run Bcrypt
-        bcrypt(BCRYPT_COST, &salt, password.as_bytes(), &mut bcrypt_output);
+/// Decrypted content of the file.
+#[derive(Deserialize)]
+struct Payload {
+    entries: Vec<Entry>,
+    #[serde(default)]
+    trash: Vec<TrashedEntry>,
+    #[serde(default)]
+    groups: BTreeSet<String>,
+    #[serde(default)]
+    settings: Settings
+}
 
-        // Decrypt
-        let secret =  match SecretMsg::from_bytes(&buffer) {
-            Some(msg) => msg,
-            None => return Database::invalid_data_error("Too few bytes (less than NONCE + ZERO bytes of SecretMsg).".to_string())
-        };
+#[derive(Serialize)]
+struct PayloadRef<'a> {
+    entries: &'a Vec<Entry>,
+    trash: &'a Vec<TrashedEntry>,
+    groups: &'a BTreeSet<String>,
+    settings: &'a Settings
+}
+
+// Files written before settings existed contain only the list of entries
+fn parse_payload(dec: &[u8]) -> DbResult<Payload> {
+    if dec.first() == Some(&b'[') {
+        Ok(Payload { entries: serde_json::from_slice(dec)?, trash: Vec::new(),
+                     groups: BTreeSet::new(), settings: Settings::default() })
+    } else {
+        Ok(serde_json::from_slice(dec)?)
+    }
+}
+
+// Volatile, so that the compiler can't skip writes to memory freed right after
+fn wipe_bytes(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
+/// Counts the bytes written to it and drops them.
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
 
-        let key = SecretKey::from_slice(&bcrypt_output);
-        let dec = key.decrypt(&secret).unwrap();
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Database {
+    kdf: KdfParams,
+    key: [u8; KEY_BYTES],
+    pub entries: Vec<Entry>,
+    /// Removed entries, see `remove`.
+    pub trash: Vec<TrashedEntry>,
+    /// Groups created explicitly, others exist only through their entries.
+    pub groups: BTreeSet<String>,
+    pub settings: Settings
+}
+
+impl Database {
+    /// New database protected with Argon2id using the default parameters.
+    #[cfg(test)]
+    pub fn empty(password: &str) -> Database {
+        Database::empty_with_kdf(password, KdfParams::argon2id()).unwrap()
+    }
+
+    pub fn empty_with_kdf(password: &str, kdf: KdfParams) -> DbResult<Database> {
+        let key = kdf.derive(password)?;
+        Ok(Database {
+            kdf,
+            key,
+            entries: Vec::new(),
+            trash: Vec::new(),
+            groups: BTreeSet::new(),
+            settings: Settings::default()
+		})
+    }
+
+    pub fn open_from_file(path: &Path, password: &str) -> DbResult<Database> {
+        // let mut file = try!(File::open(Path::new(file_path)));
+        let mut file = File::open(path)?;
+        Database::open(password, &mut file)
+    }
+
+    /// Replace the file atomically, a crash while saving leaves the previous version intact.
+    /// Use `DatabaseInFile::save` for the opened database, this doesn't keep backups nor merges.
+    pub fn save_to_file(&self, path: &Path) -> DbResult<()> {
+        atomic::write_file(path, |file| self.save(file))
+    }
+
+    /// Open a database of any supported version. Older versions are written
+    /// in the current format on the next save, keeping their KDF parameters.
+    pub fn open<T: Read>(password: &str, src: &mut T) -> DbResult<Database> {
+        let raw = format::read(src)?;
+        let key = raw.header.kdf.derive(password)?;
+        Database::decrypt(raw, key)
+	}
+
+    /// Open another file (e.g. a backup) with the key of this database, without
+    /// asking for the password again. Works only if it was encrypted with the same key.
+    pub fn open_with_same_key(&self, path: &Path) -> DbResult<Database> {
+        self.decrypt_with_same_key(&read_file(path)?)
+    }
+
+    fn decrypt_with_same_key(&self, bytes: &[u8]) -> DbResult<Database> {
+        let raw = format::read(&mut &bytes[..])?;
+        if raw.header.kdf != self.kdf {
+            return Err(DbError::WrongPassword);
+        }
+        Database::decrypt(raw, self.key)
+    }
 
-        let deserialized_entries: Vec<Entry> = serde_json::from_slice(&dec).unwrap();
+    fn decrypt(raw: RawFile, key: [u8; KEY_BYTES]) -> DbResult<Database> {
+        let mut dec = raw.decrypt(&key)?;
+        let payload = parse_payload(&dec);
+        // Holds every password in plaintext
+        wipe_bytes(&mut dec);
+        let payload = payload?;
 
+        let mut entries = payload.entries;
+        assign_missing_ids(&mut entries);
         Ok(Database{
-            bcrypt_salt: salt,
-            bcrypt_pass: bcrypt_output,
-            entries: deserialized_entries
+            kdf: raw.header.kdf,
+            key,
+            entries,
+            trash: payload.trash,
+            groups: payload.groups,
+            settings: payload.settings
         })
-	}
+    }
 
-    pub fn save<T: Write>(&self, dest: &mut T) -> io::Result<()>{
-        let serialized = serde_json::to_string(&self.entries).unwrap();
+    pub fn save<T: Write>(&self, dest: &mut T) -> DbResult<()>{
+        let payload = PayloadRef { entries: &self.entries, trash: &self.trash,
+                                   groups: &self.groups, settings: &self.settings };
+        // Measured first, so that the buffer isn't reallocated and no partial
+        // copies of the plaintext are left behind
+        let mut counter = ByteCounter(0);
+        serde_json::to_writer(&mut counter, &payload)?;
+        let mut serialized = Vec::with_capacity(counter.0);
+        let res = serde_json::to_writer(&mut serialized, &payload).map_err(From::from)
+            .and_then(|_| format::write(dest, &self.kdf, &self.key, &serialized));
+        wipe_bytes(&mut serialized);
+        res
+    }
 
-        let key = SecretKey::from_slice(&self.bcrypt_pass);
-        let enc: SecretMsg = key.encrypt(serialized.as_bytes());
+    /// Algorithm and parameters used to derive the key.
+    pub fn kdf(&self) -> &KdfParams {
+        &self.kdf
+    }
 
-        // write version
-        try!(dest.write(&[DB_VERSION]));
-        // write salt first
-        try!(dest.write(&self.bcrypt_salt));
-        try!(dest.flush());
-        // write nonce + encrypted data
-        try!(dest.write(&enc.nonce));
-        try!(dest.flush());
-        try!(dest.write(&enc.cipher));
-        try!(dest.flush());
+    /// Check the password against the key the database was opened with.
+    pub fn check_password(&self, password: &str) -> bool {
+        match self.kdf.derive(password) {
+            Ok(key) => fixed_time_eq(&key, &self.key),
+            Err(_) => false
+        }
+    }
 
+    /// Derive a new key from the password, the database is encrypted with it on the next save.
+    /// The KDF parameters should come with a fresh salt.
+    pub fn change_password(&mut self, password: &str, kdf: KdfParams) -> DbResult<()> {
+        self.key = kdf.derive(password)?;
+        self.kdf = kdf;
         Ok(())
     }
 
+    /// Drop all entries and groups and overwrite the key. Passwords and secret
+    /// fields are zeroed by their SecStrs on drop.
+    pub fn wipe(&mut self) {
+        self.entries.clear();
+        self.trash.clear();
+        // The group names tell what the vault holds too
+        self.groups.clear();
+        wipe_bytes(&mut self.key);
+    }
+
     pub fn add(&mut self, entry: Entry){
         self.entries.push(entry);
     }
 
-    pub fn get(&self, entry_title: &str) -> Option<&Entry> {
-        self.entries.iter().find(|entry| entry.title.eq(entry_title))
+    /// Entries addressed by the user, by title, by path (`group/title`) or by a prefix
+    /// of the id, in this order of precedence. More than one entry can match.
+    pub fn find(&self, query: &str) -> Vec<&Entry> {
+        find_entries(self.entries.iter().collect(), query)
+    }
+
+    /// Same as `find`, but among the removed entries.
+    pub fn find_in_trash(&self, query: &str) -> Vec<&Entry> {
+        find_entries(self.trash.iter().map(|trashed| &trashed.entry).collect(), query)
+    }
+
+    /// There is an entry with the title in the group.
+    pub fn has_title(&self, group: &str, title: &str) -> bool {
+        self.entries.iter().any(|entry| entry.group == group && entry.title == title)
     }
 
-    pub fn remove(&mut self, entry_title: &str) -> bool{
+    pub fn get(&self, entry_id: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.id.eq(entry_id))
+    }
+
+    pub fn get_mut(&mut self, entry_id: &str) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.id.eq(entry_id))
+    }
+
+    /// Move the entry to the trash.
+    pub fn remove(&mut self, entry_id: &str) -> bool{
         let pos = self.entries
             .iter()
-            .position(|entry| entry.title.eq(entry_title));
-        return match pos {
+            .position(|entry| entry.id.eq(entry_id));
+        match pos {
             Some(index) => {
-                self.entries.remove(index);
+                let entry = self.entries.remove(index);
+                self.trash.push(TrashedEntry { entry, removed: timestamp::now() });
                 true
             }
             None =>  false
         }
     }
 
-    fn invalid_data_error(text: String) -> io::Result<Database>{
-        Err(Error::new(ErrorKind::InvalidData, text))
+    /// Move the entry from the trash back among the others.
+    pub fn restore_from_trash(&mut self, entry_id: &str) -> bool {
+        match self.trash.iter().position(|trashed| trashed.entry.id == entry_id) {
+            Some(index) => {
+                let trashed = self.trash.remove(index);
+                self.entries.push(trashed.entry);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Delete the removed entries for good, returns how many there were.
+    pub fn empty_trash(&mut self) -> usize {
+        let count = self.trash.len();
+        self.trash.clear();
+        count
+    }
+
+    /// All existing groups: the created ones, the ones with entries and the groups above them.
+    /// The root group is not included.
+    pub fn all_groups(&self) -> BTreeSet<String> {
+        self.groups.iter()
+            .chain(self.entries.iter().map(|entry| &entry.group))
+            .flat_map(|path| group::ancestors(path).into_iter())
+            .collect()
+    }
+
+    pub fn group_exists(&self, path: &str) -> bool {
+        path.is_empty() || self.all_groups().contains(path)
+    }
+
+    /// No entries nor other groups inside.
+    pub fn is_group_empty(&self, path: &str) -> bool {
+        !self.entries.iter().any(|entry| group::contains(path, &entry.group)) &&
+            !self.all_groups().iter().any(|other| other != path && group::contains(path, other))
+    }
+
+    /// Rename a group, everything inside moves with it.
+    pub fn move_group(&mut self, from: &str, to: &str) {
+        let rename = |path: &str| format!("{}{}", to, &path[from.len()..]);
+        for entry in self.entries.iter_mut().filter(|entry| group::contains(from, &entry.group)) {
+            entry.group = rename(&entry.group);
+        }
+        let groups = mem::take(&mut self.groups);
+        self.groups = groups.into_iter()
+            .map(|path| if group::contains(from, &path) { rename(&path) } else { path })
+            .collect();
+        // Keep it even if empty, as it was before
+        self.groups.insert(to.to_string());
+    }
+
+    /// Drop entries which have been in the trash longer than the retention period.
+    pub fn expire_trash(&mut self) -> usize {
+        trash::expire(&mut self.trash, self.settings.trash_retention_days, timestamp::now())
     }
 
 }
@@ -153,8 +527,16 @@ impl Database {
 mod tests {
     use db::Entry;
     use db::Database;
+    use db::DatabaseInFile;
+    use db::DbError;
+    use db::lock::{self, LockStatus};
+    use db::kdf::{KdfParams, SALT_SIZE, BCRYPT_COST};
+    use db::format::{VERSION_1, VERSION_2};
+    use nacl::secretbox::SecretKey;
+    use std::env;
+    use std::fs;
     use std::io::Cursor;
-    use std::io::Read;
+    use std::os::unix::fs::PermissionsExt;
 
     #[test]
     fn test_save_and_load() {
@@ -164,7 +546,7 @@ mod tests {
             db.add(Entry::new("service_a", "name_a", "pass_a"));
             db.add(Entry::new("service_b", "name_b", "pass_b"));
             db.add(Entry::new("service_c", "name_c", "pass_c"));
-            db.save(&mut buff);
+            db.save(&mut buff).unwrap();
         }
 
         // Cursor position has to be reset before reading
@@ -172,4 +554,201 @@ mod tests {
         let db = Database::open("test", &mut buff).unwrap();
         assert_eq!(db.entries.len(), 3);
     }
+
+    #[test]
+    fn test_open_v1_and_upgrade() {
+        // Version 1 layout: version | salt | nonce | ciphertext
+        let salt = [7u8; SALT_SIZE];
+        let key = KdfParams::Bcrypt { cost: BCRYPT_COST, salt }.derive("test").unwrap();
+        let payload = r#"[{"title":"a","username":"b","password":"c"}]"#;
+        let enc = SecretKey::from_slice(&key).encrypt(payload.as_bytes());
+        let mut v1 = vec![VERSION_1];
+        v1.extend(salt.iter().cloned());
+        v1.extend(enc.as_bytes());
+
+        let db = Database::open("test", &mut Cursor::new(v1)).unwrap();
+        assert_eq!(db.entries.len(), 1);
+
+        let mut buff: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        db.save(&mut buff).unwrap();
+        assert_eq!(buff.get_ref()[0], VERSION_2);
+
+        buff.set_position(0);
+        let db = Database::open("test", &mut buff).unwrap();
+        assert_eq!(db.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_save_to_file() {
+        let dir = env::temp_dir().join(format!("rpass-test-save-{}", unsafe { ::libc::getpid() }));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("vault.db");
+
+        let mut db = Database::empty_with_kdf("test", KdfParams::scrypt()).unwrap();
+        db.save_to_file(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        // Changed permissions survive the next save
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        db.add(Entry::new("service_a", "name_a", "pass_a"));
+        db.save_to_file(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        assert_eq!(Database::open_from_file(&path, "test").unwrap().entries.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_change_password() {
+        let mut db = Database::empty_with_kdf("old", KdfParams::scrypt()).unwrap();
+        assert!(db.check_password("old"));
+        let kdf = db.kdf().with_new_salt();
+        db.change_password("new", kdf).unwrap();
+        assert!(!db.check_password("old"));
+
+        let mut buff: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        db.save(&mut buff).unwrap();
+        buff.set_position(0);
+        assert!(Database::open("old", &mut buff).is_err());
+        buff.set_position(0);
+        assert!(Database::open("new", &mut buff).is_ok());
+    }
+
+    #[test]
+    fn test_wipe() {
+        let mut db = Database::empty_with_kdf("test", KdfParams::scrypt()).unwrap();
+        db.add(Entry::new("service", "name", "pass"));
+        let id = db.entries[0].id.clone();
+        db.remove(&id);
+        db.add(Entry::new("other", "name", "pass"));
+        db.groups.insert("work".to_string());
+        db.wipe();
+        assert!(db.entries.is_empty());
+        assert!(db.trash.is_empty());
+        assert!(db.groups.is_empty());
+        assert!(!db.check_password("test"));
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let mut buff: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        Database::empty("test").save(&mut buff).unwrap();
+
+        buff.set_position(0);
+        match Database::open("wrong", &mut buff) {
+            Err(DbError::WrongPassword) => (),
+            _ => panic!("wrong password must be reported as DbError::WrongPassword")
+        }
+    }
+
+    #[test]
+    fn test_bad_header() {
+        let mut buff = Cursor::new(vec![42u8]);
+        match Database::open("test", &mut buff) {
+            Err(DbError::UnsupportedVersion(42)) => (),
+            _ => panic!("expected DbError::UnsupportedVersion")
+        }
+
+        let mut buff = Cursor::new(vec![VERSION_1, 0, 0, 0]);
+        match Database::open("test", &mut buff) {
+            Err(DbError::TruncatedHeader) => (),
+            _ => panic!("expected DbError::TruncatedHeader")
+        }
+
+        let mut buff = Cursor::new(vec![VERSION_2, 3, 0]);
+        match Database::open("test", &mut buff) {
+            Err(DbError::TruncatedHeader) => (),
+            _ => panic!("expected DbError::TruncatedHeader")
+        }
+    }
+
+    #[test]
+    fn test_save_merges_external_changes() {
+        let dir = env::temp_dir().join(format!("rpass-test-merge-{}", unsafe { ::libc::getpid() }));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("vault.db");
+
+        let lock = match lock::acquire(&path).unwrap() {
+            LockStatus::Acquired(lock) => lock,
+            LockStatus::HeldBy(_) => panic!("nobody else holds the lock")
+        };
+        let db = Database::empty_with_kdf("test", KdfParams::scrypt()).unwrap();
+        let mut file_db = DatabaseInFile::create(path.to_string_lossy().into_owned(), db, lock).unwrap();
+        assert!(!file_db.is_modified_externally().unwrap());
+
+        // Another process adds an entry behind our back
+        let mut other = Database::open_from_file(&path, "test").unwrap();
+        other.add(Entry::new("remote", "name", "pass"));
+        other.save_to_file(&path).unwrap();
+        assert!(file_db.is_modified_externally().unwrap());
+
+        file_db.db.add(Entry::new("local", "name", "pass"));
+        let report = file_db.save().unwrap();
+        assert!(report.merged);
+        assert!(report.conflicts.is_empty());
+        assert!(!file_db.is_modified_externally().unwrap());
+
+        let saved = Database::open_from_file(&path, "test").unwrap();
+        assert!(saved.has_title("", "local") && saved.has_title("", "remote"));
+        drop(file_db);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_find() {
+        let mut db = Database::empty_with_kdf("test", KdfParams::scrypt()).unwrap();
+        db.add(Entry::new("github", "alice", "pass"));
+        db.add(Entry::new("github", "bob", "pass"));
+        db.add(Entry::new("mail", "alice", "pass"));
+
+        assert_eq!(db.find("github").len(), 2);
+        let id = db.find("mail")[0].id.clone();
+        assert_eq!(db.find(&id[..6])[0].title, "mail");
+        // Too short to be taken as an id prefix
+        assert!(db.find(&id[..2]).is_empty());
+        assert!(db.remove(&id));
+        assert!(db.find("mail").is_empty());
+    }
+
+    #[test]
+    fn test_legacy_ids() {
+        let salt = [7u8; SALT_SIZE];
+        let key = KdfParams::Bcrypt { cost: BCRYPT_COST, salt }.derive("test").unwrap();
+        let payload = r#"[{"title":"a","username":"b","password":"c"},{"title":"a","username":"d","password":"e"}]"#;
+        let enc = SecretKey::from_slice(&key).encrypt(payload.as_bytes());
+        let mut v1 = vec![VERSION_1];
+        v1.extend(salt.iter().cloned());
+        v1.extend(enc.as_bytes());
+
+        // Every load assigns the same ids, duplicate titles get different ones
+        let first = Database::open("test", &mut Cursor::new(v1.clone())).unwrap();
+        let second = Database::open("test", &mut Cursor::new(v1)).unwrap();
+        assert!(!first.entries[0].id.is_empty());
+        assert!(first.entries[0].id != first.entries[1].id);
+        assert_eq!(first.entries[0].id, second.entries[0].id);
+        assert_eq!(first.entries[1].id, second.entries[1].id);
+    }
+
+    #[test]
+    fn test_groups() {
+        let mut db = Database::empty_with_kdf("test", KdfParams::scrypt()).unwrap();
+        let mut entry = Entry::new("console", "alice", "pass");
+        entry.group = "work/aws".to_string();
+        db.add(entry);
+        db.add(Entry::new("console", "bob", "pass"));
+        db.groups.insert("work/empty".to_string());
+
+        assert_eq!(db.find("work/aws/console")[0].username(), "alice");
+        assert_eq!(db.find("/console")[0].username(), "bob");
+        assert!(db.group_exists("work"));
+        assert!(!db.is_group_empty("work"));
+        assert!(db.is_group_empty("work/empty"));
+
+        db.move_group("work", "old/work");
+        assert_eq!(db.find("old/work/aws/console").len(), 1);
+        assert!(db.group_exists("old/work/empty"));
+        assert!(!db.group_exists("work"));
+    }
 }
@@ -1,41 +1,432 @@
 use secstr::SecStr;
+use timestamp::{self, Timestamp};
+use db::group;
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use rand::{Rng, OsRng};
+use std::cmp;
+use std::collections::BTreeSet;
+use std::mem;
+
+/// Random bytes of an entry id, printed as hex.
+pub const ID_BYTES: usize = 16;
+/// Length of the id prefix shown in lists.
+pub const SHORT_ID_LEN: usize = 8;
+/// Seconds, a more recent use doesn't update `last_used`. Saving the whole
+/// vault after every `show` isn't worth a more exact time.
+pub const LAST_USED_PRECISION: Timestamp = 3600;
+/// Shown instead of secret values unless they are revealed.
+pub const MASK: &str = "********";
 
 // The `derive` attribute automatically creates the implementation
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Entry {
+    /// Stable identifier, titles don't have to be unique.
+    /// Missing in files written before ids existed, see `legacy_id`.
+    #[serde(default)]
+    pub id: String,
     pub title: String,
+    /// Path of the group, empty for the root group. See `db::group`.
+    #[serde(default)]
+    pub group: String,
     username: String,
-    password: SecStr
+    password: SecStr,
+    #[serde(default)]
+    pub url: String,
+    /// Free text, can have more lines.
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    /// Additional fields defined by the user, in the order they were added.
+    #[serde(default)]
+    pub fields: Vec<CustomField>,
+    /// Previous passwords, the most recent first.
+    #[serde(default)]
+    pub history: Vec<PreviousPassword>,
+    /// Times of the changes and of the last copy or display, 0 means unknown
+    /// (entries from older files) or never used.
+    #[serde(default)]
+    pub created: Timestamp,
+    #[serde(default)]
+    pub modified: Timestamp,
+    #[serde(default)]
+    pub password_changed: Timestamp,
+    #[serde(default)]
+    pub last_used: Timestamp,
+    /// Set when a merge found conflicting changes, cleared by editing the entry.
+    #[serde(default)]
+    pub conflict: bool
 }
 
 impl Entry {
+    #[cfg(test)]
     pub fn new<S: Into<String>>(title: S, username: S, password: S) -> Entry {
+		Entry::with_password(title, username, SecStr::new(password.into()))
+	}
+
+    /// Create an entry with an already protected password.
+    pub fn with_password<S: Into<String>>(title: S, username: S, password: SecStr) -> Entry {
+		let now = timestamp::now();
 		Entry {
+			id: new_id(),
 			title: title.into(),
+			group: String::new(),
 			username: username.into(),
-			password: SecStr::new(password.into())
+			password,
+			url: String::new(),
+			notes: String::new(),
+			tags: BTreeSet::new(),
+			fields: Vec::new(),
+			history: Vec::new(),
+			created: now,
+			modified: now,
+			password_changed: now,
+			last_used: 0,
+			conflict: false
 		}
 	}
 
-    pub fn print_short_info_desc(){
-        macro_rules! row {() => ("{0: <10} | {1: <10}")};
-        println!(row!(), "Title", "Username");
+    /// Group and title, the form accepted wherever an entry is expected.
+    pub fn path(&self) -> String {
+        group::join(&self.group, &self.title)
+    }
+
+    /// Beginning of the id, enough to address the entry in practice.
+    pub fn short_id(&self) -> &str {
+        &self.id[..cmp::min(SHORT_ID_LEN, self.id.len())]
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn set_username<S: Into<String>>(&mut self, username: S){
+        self.username = username.into();
+    }
+
+    /// Call `inspect` with the plaintext password, which is wiped right after.
+    pub fn inspect_password<F, R>(&mut self, inspect: F) -> R where F: FnOnce(&str) -> R {
+        inspect_secret(&mut self.password, inspect)
+    }
+
+    /// Replace the password, the old one goes to the history which keeps at most
+    /// `history_length` passwords. Dropped SecStrs are zeroed.
+    pub fn set_password(&mut self, password: SecStr, history_length: usize){
+        let now = timestamp::now();
+        let previous = mem::replace(&mut self.password, password);
+        self.history.insert(0, PreviousPassword { password: previous, replaced: now });
+        self.trim_history(history_length);
+        self.password_changed = now;
+    }
+
+    /// Make the n-th previous password (1 is the most recent) current again,
+    /// the current one goes to the history. Returns false if there is no such password.
+    pub fn restore_password(&mut self, number: usize, history_length: usize) -> bool {
+        if number == 0 || number > self.history.len() {
+            return false;
+        }
+        let previous = self.history.remove(number - 1);
+        self.set_password(previous.password, history_length);
+        true
+    }
+
+    pub fn trim_history(&mut self, history_length: usize) {
+        self.history.truncate(history_length);
+    }
+
+    pub fn mark_modified(&mut self) {
+        self.modified = timestamp::now();
+    }
+
+    /// The password was copied or the entry displayed. Returns false if the
+    /// previous use is less than `LAST_USED_PRECISION` ago and nothing changed.
+    pub fn mark_used(&mut self) -> bool {
+        let now = timestamp::now();
+        if now < self.last_used + LAST_USED_PRECISION {
+            return false;
+        }
+        self.last_used = now;
+        true
+    }
+
+    pub fn field(&self, name: &str) -> Option<&CustomField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Add a custom field, or replace the value of an existing one with the same name.
+    pub fn set_field(&mut self, field: CustomField) {
+        match self.fields.iter().position(|existing| existing.name == field.name) {
+            Some(index) => self.fields[index] = field,
+            None => self.fields.push(field)
+        }
+    }
+
+    pub fn remove_field(&mut self, name: &str) -> bool {
+        let before = self.fields.len();
+        self.fields.retain(|field| field.name != name);
+        self.fields.len() != before
+    }
+
+    /// Tags separated by commas, for display.
+    pub fn tags_string(&self) -> String {
+        self.tags.iter().cloned().collect::<Vec<String>>().join(", ")
+    }
+
+    /// Columns shown when listing entries.
+    pub fn summary_fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("id", self.short_id().to_string()),
+            ("title", self.title.clone()),
+            ("username", self.username.clone()),
+            ("group", self.group.clone())
+        ]
+    }
+
+    /// All fields of the entry, secrets are masked unless `reveal` is set.
+    /// Empty optional fields are left out.
+    pub fn detail_fields(&mut self, reveal: bool) -> Vec<(String, String)> {
+        let password = if reveal { reveal_secret(&mut self.password) } else { MASK.to_string() };
+        let mut fields = vec![
+            ("id".to_string(), self.id.clone()),
+            ("title".to_string(), self.title.clone()),
+            ("username".to_string(), self.username.clone()),
+            ("password".to_string(), password)
+        ];
+        if !self.group.is_empty() {
+            fields.push(("group".to_string(), self.group.clone()));
+        }
+        if !self.url.is_empty() {
+            fields.push(("url".to_string(), self.url.clone()));
+        }
+        if !self.notes.is_empty() {
+            fields.push(("notes".to_string(), self.notes.clone()));
+        }
+        if !self.tags.is_empty() {
+            fields.push(("tags".to_string(), self.tags_string()));
+        }
+        for field in self.fields.iter_mut() {
+            let value = if reveal || !field.is_secret() { field.value_string() } else { MASK.to_string() };
+            fields.push((field.name.clone(), value));
+        }
+        fields.push(("created".to_string(), format_time(self.created, "unknown")));
+        fields.push(("modified".to_string(), format_time(self.modified, "unknown")));
+        fields.push(("password_changed".to_string(), format_time(self.password_changed, "unknown")));
+        fields.push(("last_used".to_string(), format_time(self.last_used, "never")));
+        if self.conflict {
+            fields.push(("conflict".to_string(), "changed in two places, check and edit to resolve".to_string()));
+        }
+        fields
+    }
+
+    /// Print a single field value without any decoration.
+    /// Returns false if there is no field with such name.
+    pub fn print_field(&mut self, field: &str) -> bool {
+        match self.field_value(field) {
+            Some(value) => {
+                println!("{}", value);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Plaintext value of a single field, None if there is no field with such name.
+    pub fn field_value(&mut self, field: &str) -> Option<String> {
+        Some(match field {
+            "id" => self.id.clone(),
+            "title" => self.title.clone(),
+            "group" => self.group.clone(),
+            "username" => self.username.clone(),
+            "password" => reveal_secret(&mut self.password),
+            "url" => self.url.clone(),
+            "notes" => self.notes.clone(),
+            "tags" => self.tags_string(),
+            "created" => format_time(self.created, "unknown"),
+            "modified" => format_time(self.modified, "unknown"),
+            "password_changed" => format_time(self.password_changed, "unknown"),
+            "last_used" => format_time(self.last_used, "never"),
+            name => match self.fields.iter_mut().find(|field| field.name == name) {
+                Some(field) => field.value_string(),
+                None => return None
+            }
+        })
+    }
+}
+
+/// Names of the built-in fields, custom fields can't use them.
+pub fn is_reserved_field(name: &str) -> bool {
+    ["id", "title", "group", "username", "password", "url", "notes", "tags", "history",
+     "created", "modified", "password_changed", "last_used", "conflict"].contains(&name)
+}
+
+/// A password replaced by a newer one.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PreviousPassword {
+    pub password: SecStr,
+    /// When it stopped being the current password.
+    pub replaced: Timestamp
+}
+
+impl PreviousPassword {
+    /// The password in plaintext, for display with --reveal.
+    pub fn reveal(&mut self) -> String {
+        reveal_secret(&mut self.password)
+    }
+}
+
+/// Value of a custom field. Secret values are kept in a `SecStr` and masked on output.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum FieldValue {
+    Plain(String),
+    Secret(SecStr)
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CustomField {
+    pub name: String,
+    pub value: FieldValue
+}
+
+impl CustomField {
+    pub fn plain<S: Into<String>>(name: S, value: S) -> CustomField {
+        CustomField { name: name.into(), value: FieldValue::Plain(value.into()) }
+    }
+
+    pub fn secret<S: Into<String>>(name: S, value: SecStr) -> CustomField {
+        CustomField { name: name.into(), value: FieldValue::Secret(value) }
+    }
+
+    pub fn is_secret(&self) -> bool {
+        match self.value {
+            FieldValue::Secret(_) => true,
+            FieldValue::Plain(_) => false
+        }
+    }
+
+    /// Call `inspect` with the plaintext value, a secret one is wiped right after.
+    pub fn inspect_value<F, R>(&mut self, inspect: F) -> R where F: FnOnce(&str) -> R {
+        match self.value {
+            FieldValue::Plain(ref value) => inspect(value),
+            FieldValue::Secret(ref mut secret) => inspect_secret(secret, inspect)
+        }
+    }
+
+    /// The value in plaintext, a secret one is decrypted only for the copy.
+    pub fn value_string(&mut self) -> String {
+        match self.value {
+            FieldValue::Plain(ref value) => value.clone(),
+            FieldValue::Secret(ref mut secret) => reveal_secret(secret)
+        }
+    }
+}
+
+/// A time for display, `missing` if it's unknown.
+pub fn format_time(time: Timestamp, missing: &str) -> String {
+    if time == 0 { missing.to_string() } else { timestamp::format(time) }
+}
+
+fn inspect_secret<F, R>(secret: &mut SecStr, inspect: F) -> R where F: FnOnce(&str) -> R {
+    secret.unlock();
+    let result = inspect(&secret.string);
+    secret.delete();
+    result
+}
+
+fn reveal_secret(secret: &mut SecStr) -> String {
+    secret.unlock();
+    let plaintext = secret.string.clone();
+    secret.delete();
+    plaintext
+}
+
+/// Random id for a new entry.
+pub fn new_id() -> String {
+    let mut bytes = [0u8; ID_BYTES];
+    OsRng::new().unwrap().fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+/// Id for an entry loaded from a file without ids. It's derived from the title
+/// and the number of entries with the same title before it, so that all copies
+/// of the file (e.g. the base and the remote side of a merge) agree on it.
+pub fn legacy_id(title: &str, occurrence: usize) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input_str(&format!("rpass legacy id\0{}\0{}", title, occurrence));
+    let mut digest = [0u8; 32];
+    hasher.result(&mut digest);
+    to_hex(&digest[..ID_BYTES])
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use db::Entry;
+    use super::{legacy_id, ID_BYTES, LAST_USED_PRECISION, CustomField};
+    use secstr::SecStr;
+    use serde_json;
+
+    #[test]
+    fn test_ids() {
+        let a = Entry::new("service", "name", "pass");
+        let b = Entry::new("service", "name", "pass");
+        assert_eq!(a.id.len(), ID_BYTES * 2);
+        assert!(a.id != b.id);
+        assert_eq!(a.short_id(), &a.id[..8]);
+
+        assert_eq!(legacy_id("service", 0), legacy_id("service", 0));
+        assert!(legacy_id("service", 0) != legacy_id("service", 1));
     }
 
-    pub fn print_short_info(&self){
-        macro_rules! row {() => ("{0: <10} | {1: <10}")};
-        println!(row!(), self.title, self.username);
+    #[test]
+    fn test_mark_used() {
+        let mut entry = Entry::new("service", "name", "pass");
+        assert!(entry.mark_used());
+        // Too soon to be worth a save
+        assert!(!entry.mark_used());
+
+        entry.last_used -= LAST_USED_PRECISION;
+        assert!(entry.mark_used());
     }
 
-    pub fn print_full_info(&self){
-        macro_rules! row {() => ("{0: <10} {1: <10}")};
+    #[test]
+    fn test_optional_fields() {
+        // Entries written before the optional fields existed
+        let entry: Entry = serde_json::from_str(r#"{"title":"a","username":"b","password":"c"}"#).unwrap();
+        assert!(entry.url.is_empty() && entry.tags.is_empty() && entry.fields.is_empty());
+
+        let mut entry = Entry::new("service", "name", "pass");
+        entry.tags.insert("work".to_string());
+        entry.set_field(CustomField::plain("pin hint", "birthday"));
+        entry.set_field(CustomField::secret("pin", SecStr::new("1234".to_string())));
 
-        println!(row!(), "Title:", self.title);
-        println!(row!(), "Username:", self.username);
-        println!(row!(), "Password:", self.password);
+        let masked = entry.detail_fields(false);
+        assert!(masked.contains(&("tags".to_string(), "work".to_string())));
+        assert!(masked.contains(&("pin hint".to_string(), "birthday".to_string())));
+        assert!(!masked.iter().any(|(_, value)| value == "1234"));
+        assert!(entry.detail_fields(true).contains(&("pin".to_string(), "1234".to_string())));
+
+        let json = serde_json::to_string(&entry).unwrap();
+        let loaded: Entry = serde_json::from_str(&json).unwrap();
+        assert!(loaded == entry);
     }
 
-    pub fn copy_pass_to_clipboard(&self){
-        println!("Error: NOT IMPLEMENTED YET");
+    #[test]
+    fn test_password_history() {
+        let mut entry = Entry::new("service", "name", "first");
+        entry.set_password(SecStr::new("second".to_string()), 2);
+        entry.set_password(SecStr::new("third".to_string()), 2);
+        entry.set_password(SecStr::new("fourth".to_string()), 2);
+        let history: Vec<String> = entry.history.iter_mut().map(|previous| previous.reveal()).collect();
+        assert_eq!(history, vec!["third".to_string(), "second".to_string()]);
+
+        assert!(entry.restore_password(2, 2));
+        assert!(!entry.restore_password(3, 2));
+        assert!(entry.detail_fields(true).contains(&("password".to_string(), "second".to_string())));
+        let history: Vec<String> = entry.history.iter_mut().map(|previous| previous.reveal()).collect();
+        assert_eq!(history, vec!["fourth".to_string(), "third".to_string()]);
     }
 }
@@ -0,0 +1,102 @@
+use std::error;
+use std::fmt;
+use std::io;
+use serde_json;
+use nacl::secretbox::SecretBoxError;
+
+/// Everything that can go wrong while reading or writing a database file.
+#[derive(Debug)]
+pub enum DbError {
+    /// The secretbox could not be verified, i.e. the master password is wrong
+    /// or the ciphertext was tampered with.
+    WrongPassword,
+    /// The file was written by a version of rpass we don't understand.
+    UnsupportedVersion(u8),
+    /// Key derivation function with this id is not known.
+    UnknownKdf(u8),
+    /// Cipher with this id is not known.
+    UnknownCipher(u8),
+    /// KDF parameters are out of the accepted range.
+    InvalidKdfParams(String),
+    /// The file ends before the header (version, KDF parameters, nonce) is complete.
+    TruncatedHeader,
+    /// The decrypted payload is not a valid list of entries.
+    MalformedPayload(String),
+    /// The database was opened read-only, because another process holds the lock.
+    ReadOnly,
+    /// The file was changed by somebody else in a way we can't merge (e.g. a new master password).
+    ConcurrentModification,
+    Io(io::Error)
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DbError::WrongPassword =>
+                write!(f, "wrong master password or the database file is corrupted"),
+            DbError::UnsupportedVersion(version) =>
+                write!(f, "cannot process database version {}", version),
+            DbError::UnknownKdf(id) =>
+                write!(f, "unknown key derivation function {}", id),
+            DbError::UnknownCipher(id) =>
+                write!(f, "unknown cipher {}", id),
+            DbError::InvalidKdfParams(ref params) =>
+                write!(f, "invalid key derivation parameters: {}", params),
+            DbError::TruncatedHeader =>
+                write!(f, "database file is truncated, header is incomplete"),
+            DbError::MalformedPayload(ref why) =>
+                write!(f, "decrypted data are malformed: {}", why),
+            DbError::ReadOnly =>
+                write!(f, "database is opened read-only, changes cannot be saved"),
+            DbError::ConcurrentModification =>
+                write!(f, "database file was changed by somebody else and cannot be merged, open it again"),
+            DbError::Io(ref err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl error::Error for DbError {
+    fn description(&self) -> &str {
+        match *self {
+            DbError::WrongPassword => "wrong master password",
+            DbError::UnsupportedVersion(_) => "unsupported database version",
+            DbError::UnknownKdf(_) => "unknown key derivation function",
+            DbError::UnknownCipher(_) => "unknown cipher",
+            DbError::InvalidKdfParams(_) => "invalid key derivation parameters",
+            DbError::TruncatedHeader => "truncated header",
+            DbError::MalformedPayload(_) => "malformed payload",
+            DbError::ReadOnly => "database is read-only",
+            DbError::ConcurrentModification => "concurrent modification",
+            DbError::Io(_) => "I/O error"
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            DbError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for DbError {
+    fn from(err: io::Error) -> DbError {
+        DbError::Io(err)
+    }
+}
+
+impl From<SecretBoxError> for DbError {
+    fn from(err: SecretBoxError) -> DbError {
+        match err {
+            SecretBoxError::VerificationFail => DbError::WrongPassword
+        }
+    }
+}
+
+impl From<serde_json::Error> for DbError {
+    fn from(err: serde_json::Error) -> DbError {
+        DbError::MalformedPayload(format!("{}", err))
+    }
+}
+
+pub type DbResult<T> = Result<T, DbError>;
@@ -0,0 +1,232 @@
+//!
+//! On-disk layout of the database file.
+//!
+//! Version 1 (read only):
+//!
+//! ```text
+//! version (1) | bcrypt salt (16) | nonce (24) | ciphertext
+//! ```
+//!
+//! Version 2:
+//!
+//! ```text
+//! version (1) | header length (2) | cipher id (1) | kdf id (1) |
+//! kdf params length (2) | kdf params | header MAC (32) | nonce (24) | ciphertext
+//! ```
+//!
+//! Header length covers everything from the version byte up to and including
+//! the MAC, numbers are little endian. The MAC is HMAC-SHA256 of the header
+//! bytes before it, keyed with a subkey of the secretbox key. So tampering with
+//! the KDF parameters or the cipher id is detected before anything is decrypted.
+//!
+
+use std::io::{Read, Write, ErrorKind};
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use nacl::secretbox::{SecretKey, SecretMsg, KEY_BYTES};
+use db::error::{DbError, DbResult};
+use db::kdf::{KdfParams, SALT_SIZE, BCRYPT_COST};
+
+pub const VERSION_1: u8 = 1;
+pub const VERSION_2: u8 = 2;
+pub const CURRENT_VERSION: u8 = VERSION_2;
+
+/// XSalsa20 + Poly1305 of NaCl secretbox.
+pub const CIPHER_SECRETBOX: u8 = 1;
+
+const MAC_BYTES: usize = 32;
+// version + header length + cipher id + kdf id + kdf params length
+const FIXED_HEADER_BYTES: usize = 1 + 2 + 1 + 1 + 2;
+const HEADER_MAC_CONTEXT: &[u8] = b"rpass v2 header mac";
+
+/// Everything needed to decrypt the payload, except the password.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub version: u8,
+    pub cipher: u8,
+    pub kdf: KdfParams
+}
+
+/// Header, header bytes as read from the file (needed to verify the MAC)
+/// and the encrypted payload.
+pub struct RawFile {
+    pub header: Header,
+    header_bytes: Vec<u8>,
+    secret: SecretMsg
+}
+
+impl RawFile {
+    /// Check the header MAC with the derived key and decrypt the payload.
+    pub fn decrypt(&self, key: &[u8; KEY_BYTES]) -> DbResult<Vec<u8>> {
+        if self.header.version >= VERSION_2 {
+            let (signed, mac) = self.header_bytes.split_at(self.header_bytes.len() - MAC_BYTES);
+            // A wrong password gives a wrong MAC key, so both look the same
+            if !fixed_time_eq(&header_mac(key, signed), mac) {
+                return Err(DbError::WrongPassword);
+            }
+        }
+        let plaintext = SecretKey::from_slice(key).decrypt(&self.secret)?;
+        Ok(plaintext)
+    }
+}
+
+/// Read the header and the encrypted payload of any supported version.
+pub fn read<T: Read>(src: &mut T) -> DbResult<RawFile> {
+    let mut version = [0u8; 1];
+    read_header_part(src, &mut version)?;
+
+    let (header, header_bytes) = match version[0] {
+        VERSION_1 => read_header_v1(src)?,
+        VERSION_2 => read_header_v2(src)?,
+        other => return Err(DbError::UnsupportedVersion(other))
+    };
+
+    let mut buffer = Vec::new();
+    src.read_to_end(&mut buffer)?;
+    let secret = match SecretMsg::from_bytes(&buffer) {
+        Some(msg) => msg,
+        // Too few bytes (less than NONCE + ZERO bytes of SecretMsg)
+        None => return Err(DbError::TruncatedHeader)
+    };
+
+    Ok(RawFile { header, header_bytes, secret })
+}
+
+/// Encrypt the payload and write it with a version 2 header.
+pub fn write<T: Write>(dest: &mut T, kdf: &KdfParams, key: &[u8; KEY_BYTES], payload: &[u8]) -> DbResult<()> {
+    let kdf_bytes = kdf.to_bytes();
+    let header_len = FIXED_HEADER_BYTES + kdf_bytes.len() + MAC_BYTES;
+
+    let mut header = Vec::with_capacity(header_len);
+    header.push(CURRENT_VERSION);
+    header.extend(u16_to_bytes(header_len as u16).iter().cloned());
+    header.push(CIPHER_SECRETBOX);
+    header.push(kdf.id());
+    header.extend(u16_to_bytes(kdf_bytes.len() as u16).iter().cloned());
+    header.extend(kdf_bytes.iter().cloned());
+    let mac = header_mac(key, &header);
+    header.extend(mac.iter().cloned());
+
+    let enc = SecretKey::from_slice(key).encrypt(payload);
+
+    dest.write_all(&header)?;
+    dest.write_all(&enc.nonce)?;
+    dest.write_all(&enc.cipher)?;
+    dest.flush()?;
+    Ok(())
+}
+
+fn read_header_v1<T: Read>(src: &mut T) -> DbResult<(Header, Vec<u8>)> {
+    let mut salt = [0u8; SALT_SIZE];
+    read_header_part(src, &mut salt)?;
+
+    let mut header_bytes = vec![VERSION_1];
+    header_bytes.extend(salt.iter().cloned());
+    Ok((Header {
+        version: VERSION_1,
+        cipher: CIPHER_SECRETBOX,
+        kdf: KdfParams::Bcrypt { cost: BCRYPT_COST, salt }
+    }, header_bytes))
+}
+
+fn read_header_v2<T: Read>(src: &mut T) -> DbResult<(Header, Vec<u8>)> {
+    let mut len_bytes = [0u8; 2];
+    read_header_part(src, &mut len_bytes)?;
+    let header_len = u16_from_bytes(&len_bytes) as usize;
+    if header_len < FIXED_HEADER_BYTES + MAC_BYTES {
+        return Err(DbError::TruncatedHeader);
+    }
+
+    let mut header_bytes = vec![0u8; header_len];
+    header_bytes[0] = VERSION_2;
+    header_bytes[1] = len_bytes[0];
+    header_bytes[2] = len_bytes[1];
+    read_header_part(src, &mut header_bytes[3..])?;
+
+    let cipher = header_bytes[3];
+    if cipher != CIPHER_SECRETBOX {
+        return Err(DbError::UnknownCipher(cipher));
+    }
+    let kdf_id = header_bytes[4];
+    let kdf_len = u16_from_bytes(&header_bytes[5..7]) as usize;
+    let kdf_end = FIXED_HEADER_BYTES + kdf_len;
+    // Fields added in later versions may follow the kdf params, they are covered by the MAC
+    if kdf_end + MAC_BYTES > header_len {
+        return Err(DbError::TruncatedHeader);
+    }
+    let kdf = KdfParams::from_bytes(kdf_id, &header_bytes[FIXED_HEADER_BYTES..kdf_end])?;
+
+    Ok((Header { version: VERSION_2, cipher, kdf }, header_bytes))
+}
+
+fn header_mac(key: &[u8; KEY_BYTES], header: &[u8]) -> [u8; MAC_BYTES] {
+    // Don't use the encryption key directly for a different primitive
+    let mut subkey = [0u8; MAC_BYTES];
+    let mut hmac = Hmac::new(Sha256::new(), key);
+    hmac.input(HEADER_MAC_CONTEXT);
+    hmac.raw_result(&mut subkey);
+
+    let mut mac = [0u8; MAC_BYTES];
+    let mut hmac = Hmac::new(Sha256::new(), &subkey);
+    hmac.input(header);
+    hmac.raw_result(&mut mac);
+    mac
+}
+
+// Fills the whole buffer, a premature end of file means the header is cut off
+fn read_header_part<T: Read>(src: &mut T, buf: &mut [u8]) -> DbResult<()> {
+    match src.read_exact(buf) {
+        Ok(()) => Ok(()),
+        Err(ref why) if why.kind() == ErrorKind::UnexpectedEof => Err(DbError::TruncatedHeader),
+        Err(why) => Err(DbError::Io(why))
+    }
+}
+
+fn u16_to_bytes(value: u16) -> [u8; 2] {
+    [value as u8, (value >> 8) as u8]
+}
+
+fn u16_from_bytes(bytes: &[u8]) -> u16 {
+    (bytes[0] as u16) | (bytes[1] as u16) << 8
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use db::kdf::KdfParams;
+    use db::error::DbError;
+    use super::{read, write, VERSION_2};
+
+    #[test]
+    fn test_header_roundtrip() {
+        let kdf = KdfParams::bcrypt();
+        let key = kdf.derive("test").unwrap();
+        let mut buff = Cursor::new(vec![]);
+        write(&mut buff, &kdf, &key, b"payload").unwrap();
+
+        buff.set_position(0);
+        let raw = read(&mut buff).unwrap();
+        assert_eq!(raw.header.version, VERSION_2);
+        assert_eq!(raw.header.kdf, kdf);
+        assert_eq!(raw.decrypt(&key).unwrap(), b"payload".to_vec());
+    }
+
+    #[test]
+    fn test_header_tampering() {
+        let kdf = KdfParams::bcrypt();
+        let key = kdf.derive("test").unwrap();
+        let mut buff = Cursor::new(vec![]);
+        write(&mut buff, &kdf, &key, b"payload").unwrap();
+
+        // Lower the bcrypt cost stored in the kdf params
+        let mut bytes = buff.into_inner();
+        bytes[7] -= 1;
+        let raw = read(&mut Cursor::new(bytes)).unwrap();
+        match raw.decrypt(&key) {
+            Err(DbError::WrongPassword) => (),
+            _ => panic!("tampered header must not be accepted")
+        }
+    }
+}
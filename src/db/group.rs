@@ -0,0 +1,82 @@
+//!
+//! Groups of entries, addressed by paths like `work/aws/prod`.
+//!
+//! Every entry belongs to one group, the root group has the empty path. A group
+//! exists while it contains entries or other groups, or after it was created
+//! explicitly with `mkdir`.
+//!
+
+pub const SEPARATOR: char = '/';
+
+/// Canonical form of a user supplied path, without leading, trailing or repeated separators.
+pub fn normalize(path: &str) -> Result<String, String> {
+    let components: Vec<&str> = path.split(SEPARATOR).filter(|component| !component.is_empty()).collect();
+    for component in components.iter() {
+        if *component == "." || *component == ".." {
+            return Err(format!("Invalid group '{}', '.' and '..' are not supported.", path));
+        }
+    }
+    Ok(components.join("/"))
+}
+
+/// Last component of the path.
+pub fn name(path: &str) -> &str {
+    match path.rfind(SEPARATOR) {
+        Some(index) => &path[index + 1..],
+        None => path
+    }
+}
+
+/// The group containing this one, None for the root.
+pub fn parent(path: &str) -> Option<&str> {
+    if path.is_empty() {
+        return None;
+    }
+    Some(match path.rfind(SEPARATOR) {
+        Some(index) => &path[..index],
+        None => ""
+    })
+}
+
+pub fn join(group: &str, name: &str) -> String {
+    if group.is_empty() { name.to_string() } else { format!("{}{}{}", group, SEPARATOR, name) }
+}
+
+/// The path itself and all groups above it, except the root.
+pub fn ancestors(path: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = path;
+    while !current.is_empty() {
+        result.push(current.to_string());
+        current = parent(current).unwrap_or("");
+    }
+    result
+}
+
+/// The path is the group itself or lies anywhere below it.
+pub fn contains(group: &str, path: &str) -> bool {
+    group.is_empty() || path == group ||
+        (path.starts_with(group) && path[group.len()..].starts_with(SEPARATOR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, name, parent, ancestors, contains};
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(normalize("/work//aws/prod/").unwrap(), "work/aws/prod");
+        assert_eq!(normalize("/").unwrap(), "");
+        assert!(normalize("work/../aws").is_err());
+
+        assert_eq!(name("work/aws"), "aws");
+        assert_eq!(parent("work/aws"), Some("work"));
+        assert_eq!(parent("work"), Some(""));
+        assert_eq!(parent(""), None);
+        assert_eq!(ancestors("work/aws/prod"), vec!["work/aws/prod", "work/aws", "work"]);
+
+        assert!(contains("", "work"));
+        assert!(contains("work", "work/aws"));
+        assert!(!contains("work", "workshop"));
+    }
+}
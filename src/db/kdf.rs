@@ -0,0 +1,293 @@
+//!
+//! Key derivation functions turning the master password into the secretbox key.
+//!
+//! The algorithm and its parameters are stored in the file header, so they can
+//! change between databases and over time.
+//!
+
+use std::ptr;
+use rand::{Rng, OsRng};
+use argon2::{self, Config, ThreadMode, Variant, Version};
+use crypto::bcrypt::bcrypt;
+use crypto::scrypt::{scrypt, ScryptParams};
+use nacl::secretbox::KEY_BYTES;
+use db::error::{DbError, DbResult};
+
+pub const KDF_BCRYPT: u8 = 1;
+pub const KDF_ARGON2ID: u8 = 2;
+pub const KDF_SCRYPT: u8 = 3;
+
+pub const SALT_SIZE: usize = 16;
+/// Bcrypt produces 24 bytes, the rest of the key stays zero.
+const BCRYPT_OUTPUT_SIZE: usize = 24;
+/// Bcrypt ignores everything past the first 72 bytes of the password.
+const BCRYPT_MAX_PASSWORD: usize = 72;
+pub const BCRYPT_COST: u32 = 10;
+
+pub const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+pub const ARGON2_ITERATIONS: u32 = 3;
+pub const ARGON2_PARALLELISM: u32 = 1;
+// Upper bounds, so a crafted header can't make us allocate or compute forever
+const ARGON2_MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+const MAX_ITERATIONS: u32 = 1000;
+
+pub const SCRYPT_LOG_N: u8 = 15;
+pub const SCRYPT_R: u32 = 8;
+pub const SCRYPT_P: u32 = 1;
+const SCRYPT_MAX_LOG_N: u8 = 24;
+/// The same limit as for argon2id.
+const SCRYPT_MAX_MEMORY_KIB: u64 = ARGON2_MAX_MEMORY_KIB as u64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KdfParams {
+    /// Only for databases created by older versions.
+    Bcrypt { cost: u32, salt: [u8; SALT_SIZE] },
+    Argon2id { memory_kib: u32, iterations: u32, parallelism: u32, salt: [u8; SALT_SIZE] },
+    Scrypt { log_n: u8, r: u32, p: u32, salt: [u8; SALT_SIZE] }
+}
+
+impl KdfParams {
+    /// Bcrypt with the cost used by the version 1 files and a fresh salt.
+    #[cfg(test)]
+    pub fn bcrypt() -> KdfParams {
+        KdfParams::Bcrypt { cost: BCRYPT_COST, salt: random_salt() }
+    }
+
+    /// Argon2id with the default parameters.
+    #[cfg(test)]
+    pub fn argon2id() -> KdfParams {
+        KdfParams::Argon2id {
+            memory_kib: ARGON2_MEMORY_KIB,
+            iterations: ARGON2_ITERATIONS,
+            parallelism: ARGON2_PARALLELISM,
+            salt: random_salt()
+        }
+    }
+
+    #[cfg(test)]
+    pub fn scrypt() -> KdfParams {
+        KdfParams::Scrypt { log_n: SCRYPT_LOG_N, r: SCRYPT_R, p: SCRYPT_P, salt: random_salt() }
+    }
+
+    pub fn id(&self) -> u8 {
+        match *self {
+            KdfParams::Bcrypt { .. } => KDF_BCRYPT,
+            KdfParams::Argon2id { .. } => KDF_ARGON2ID,
+            KdfParams::Scrypt { .. } => KDF_SCRYPT
+        }
+    }
+
+    /// The same algorithm and parameters with a new random salt.
+    pub fn with_new_salt(&self) -> KdfParams {
+        let mut params = self.clone();
+        match params {
+            KdfParams::Bcrypt { ref mut salt, .. } |
+            KdfParams::Argon2id { ref mut salt, .. } |
+            KdfParams::Scrypt { ref mut salt, .. } => *salt = random_salt()
+        }
+        params
+    }
+
+    /// Check the parameters are within the limits the algorithms (and we) accept.
+    pub fn validate(&self) -> DbResult<()> {
+        let valid = match *self {
+            KdfParams::Bcrypt { cost, .. } => (4..=31).contains(&cost),
+            KdfParams::Argon2id { memory_kib, iterations, parallelism, .. } =>
+                (1..=64).contains(&parallelism) &&
+                memory_kib >= 8 * parallelism && memory_kib <= ARGON2_MAX_MEMORY_KIB &&
+                (1..=MAX_ITERATIONS).contains(&iterations),
+            // ScryptParams::new panics unless N < 2^(16 r), it needs 128 r N bytes
+            KdfParams::Scrypt { log_n, r, p, .. } =>
+                (1..=SCRYPT_MAX_LOG_N).contains(&log_n) && (1..=32).contains(&r) && (1..=16).contains(&p) &&
+                (log_n as u32) < r * 16 && (128 * r as u64) << log_n <= SCRYPT_MAX_MEMORY_KIB * 1024
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(DbError::InvalidKdfParams(self.describe()))
+        }
+    }
+
+    /// Derive the secretbox key. The parameters have to be valid, see `validate`.
+    /// Bcrypt can't take an empty password, so no such database exists and the
+    /// password is wrong.
+    pub fn derive(&self, password: &str) -> DbResult<[u8; KEY_BYTES]> {
+        let mut key = [0u8; KEY_BYTES];
+        match *self {
+            KdfParams::Bcrypt { cost, ref salt } => {
+                if password.is_empty() {
+                    return Err(DbError::WrongPassword);
+                }
+                let password = password.as_bytes();
+                let len = if password.len() > BCRYPT_MAX_PASSWORD { BCRYPT_MAX_PASSWORD } else { password.len() };
+                bcrypt(cost, salt, &password[..len], &mut key[..BCRYPT_OUTPUT_SIZE]);
+            },
+            KdfParams::Argon2id { memory_kib, iterations, parallelism, ref salt } => {
+                let config = Config {
+                    variant: Variant::Argon2id,
+                    version: Version::Version13,
+                    mem_cost: memory_kib,
+                    time_cost: iterations,
+                    lanes: parallelism,
+                    thread_mode: ThreadMode::from_threads(parallelism),
+                    secret: &[],
+                    ad: &[],
+                    hash_length: KEY_BYTES as u32
+                };
+                let mut hash = argon2::hash_raw(password.as_bytes(), salt, &config)
+                    .expect("argon2 parameters are validated");
+                key.copy_from_slice(&hash);
+                unsafe { ptr::write_bytes(hash.as_mut_ptr(), 0, hash.len()); }
+            },
+            KdfParams::Scrypt { log_n, r, p, ref salt } => {
+                scrypt(password.as_bytes(), salt, &ScryptParams::new(log_n, r, p), &mut key);
+            }
+        }
+        Ok(key)
+    }
+
+    /// Serialized parameters as stored in the header, without the algorithm id.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match *self {
+            KdfParams::Bcrypt { cost, ref salt } => {
+                out.extend(u32_to_bytes(cost).iter().cloned());
+                out.extend(salt.iter().cloned());
+            },
+            KdfParams::Argon2id { memory_kib, iterations, parallelism, ref salt } => {
+                out.extend(u32_to_bytes(memory_kib).iter().cloned());
+                out.extend(u32_to_bytes(iterations).iter().cloned());
+                out.extend(u32_to_bytes(parallelism).iter().cloned());
+                out.extend(salt.iter().cloned());
+            },
+            KdfParams::Scrypt { log_n, r, p, ref salt } => {
+                out.push(log_n);
+                out.extend(u32_to_bytes(r).iter().cloned());
+                out.extend(u32_to_bytes(p).iter().cloned());
+                out.extend(salt.iter().cloned());
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(id: u8, bytes: &[u8]) -> DbResult<KdfParams> {
+        let params = match id {
+            KDF_BCRYPT => {
+                if bytes.len() != 4 + SALT_SIZE {
+                    return Err(DbError::TruncatedHeader);
+                }
+                KdfParams::Bcrypt { cost: u32_from_bytes(&bytes[..4]), salt: salt_from_bytes(&bytes[4..]) }
+            },
+            KDF_ARGON2ID => {
+                if bytes.len() != 12 + SALT_SIZE {
+                    return Err(DbError::TruncatedHeader);
+                }
+                KdfParams::Argon2id {
+                    memory_kib: u32_from_bytes(&bytes[..4]),
+                    iterations: u32_from_bytes(&bytes[4..8]),
+                    parallelism: u32_from_bytes(&bytes[8..12]),
+                    salt: salt_from_bytes(&bytes[12..])
+                }
+            },
+            KDF_SCRYPT => {
+                if bytes.len() != 9 + SALT_SIZE {
+                    return Err(DbError::TruncatedHeader);
+                }
+                KdfParams::Scrypt {
+                    log_n: bytes[0],
+                    r: u32_from_bytes(&bytes[1..5]),
+                    p: u32_from_bytes(&bytes[5..9]),
+                    salt: salt_from_bytes(&bytes[9..])
+                }
+            },
+            _ => return Err(DbError::UnknownKdf(id))
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            KdfParams::Bcrypt { cost, .. } => format!("bcrypt (cost {})", cost),
+            KdfParams::Argon2id { memory_kib, iterations, parallelism, .. } =>
+                format!("argon2id (memory {} MiB, {} iterations, parallelism {})",
+                        memory_kib / 1024, iterations, parallelism),
+            KdfParams::Scrypt { log_n, r, p, .. } => format!("scrypt (N 2^{}, r {}, p {})", log_n, r, p)
+        }
+    }
+}
+
+pub fn random_salt() -> [u8; SALT_SIZE] {
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng::new().unwrap().fill_bytes(&mut salt);
+    salt
+}
+
+fn salt_from_bytes(bytes: &[u8]) -> [u8; SALT_SIZE] {
+    let mut salt = [0u8; SALT_SIZE];
+    salt.copy_from_slice(bytes);
+    salt
+}
+
+pub fn u32_to_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+pub fn u32_from_bytes(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+#[cfg(test)]
+mod tests {
+    use db::error::DbError;
+    use super::{KdfParams, random_salt};
+
+    #[test]
+    fn test_params_roundtrip() {
+        for params in &[KdfParams::bcrypt(), KdfParams::argon2id(), KdfParams::scrypt()] {
+            let parsed = KdfParams::from_bytes(params.id(), &params.to_bytes()).unwrap();
+            assert_eq!(&parsed, params);
+            assert!(KdfParams::from_bytes(params.id(), &params.to_bytes()[1..]).is_err());
+        }
+        assert!(KdfParams::from_bytes(99, &KdfParams::bcrypt().to_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        let params = KdfParams::Argon2id { memory_kib: 1, iterations: 1, parallelism: 1, salt: random_salt() };
+        assert!(params.validate().is_err());
+        assert!(KdfParams::from_bytes(params.id(), &params.to_bytes()).is_err());
+
+        // Within the ranges of the single values, but rust-crypto would panic or we'd need 64 GiB
+        for &(log_n, r, p) in &[(20, 1, 1), (24, 32, 16)] {
+            let params = KdfParams::Scrypt { log_n, r, p, salt: random_salt() };
+            match KdfParams::from_bytes(params.id(), &params.to_bytes()) {
+                Err(DbError::InvalidKdfParams(_)) => (),
+                other => panic!("{} {} {} gave {:?}", log_n, r, p, other)
+            }
+        }
+    }
+
+    #[test]
+    fn test_derive() {
+        let params = KdfParams::Argon2id { memory_kib: 64, iterations: 1, parallelism: 1, salt: random_salt() };
+        assert_eq!(params.derive("test").unwrap(), params.derive("test").unwrap());
+        assert!(params.derive("test").unwrap() != params.derive("tesT").unwrap());
+        assert!(params.derive("test").unwrap() != params.with_new_salt().derive("test").unwrap());
+
+        // bcrypt used to panic with passwords longer than 72 bytes
+        let long: String = (0..100).map(|_| 'x').collect();
+        KdfParams::bcrypt().derive(&long).unwrap();
+    }
+
+    #[test]
+    fn test_empty_password() {
+        // bcrypt panics with an empty password
+        match KdfParams::bcrypt().derive("") {
+            Err(DbError::WrongPassword) => (),
+            other => panic!("unexpected {:?}", other)
+        }
+        let params = KdfParams::Argon2id { memory_kib: 64, iterations: 1, parallelism: 1, salt: random_salt() };
+        assert!(params.derive("").is_ok());
+    }
+}
@@ -0,0 +1,140 @@
+//!
+//! Advisory locking of a vault opened for writing.
+//!
+//! The lock is an exclusive `flock` on `<vault>.lock`, which holds `pid@host`
+//! of the owner so others can tell who has it. The kernel releases a flock when
+//! its process dies, so a lock file we can lock is stale by definition.
+//!
+
+use std::ffi::CStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write, Seek, SeekFrom};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use libc;
+use db::atomic::NEW_FILE_MODE;
+
+/// Held for the whole session, the lock is released on drop.
+pub struct VaultLock {
+    file: File,
+    path: PathBuf,
+    /// Owner of a lock left behind by a process which didn't clean up.
+    pub stale_owner: Option<String>
+}
+
+pub enum LockStatus {
+    Acquired(VaultLock),
+    /// Somebody else holds the lock, contains `pid@host` of the owner.
+    HeldBy(String)
+}
+
+pub fn lock_path(vault: &Path) -> PathBuf {
+    let name = vault.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    vault.with_file_name(format!("{}.lock", name))
+}
+
+pub fn acquire(vault: &Path) -> io::Result<LockStatus> {
+    let path = lock_path(vault);
+    loop {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .mode(NEW_FILE_MODE)
+            .open(&path)?;
+
+        let previous_owner = read_owner(&mut file);
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+                return Ok(LockStatus::HeldBy(previous_owner.unwrap_or("unknown process".to_string())));
+            }
+            return Err(err);
+        }
+
+        // The previous owner could have removed the file between our open and flock,
+        // then we hold a lock nobody else can see. Start over with the new file.
+        if !same_file(&file, &path) {
+            continue;
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(owner_id().as_bytes())?;
+        file.sync_all()?;
+
+        return Ok(LockStatus::Acquired(VaultLock { file, path, stale_owner: previous_owner }));
+    }
+}
+
+impl Drop for VaultLock {
+    fn drop(&mut self) {
+        // Remove the file while still holding the lock, the flock goes away with the descriptor
+        let _ = fs::remove_file(&self.path);
+        unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN); }
+    }
+}
+
+fn read_owner(file: &mut File) -> Option<String> {
+    let mut owner = String::new();
+    match file.read_to_string(&mut owner) {
+        Ok(_) if !owner.trim().is_empty() => Some(owner.trim().to_string()),
+        _ => None
+    }
+}
+
+fn same_file(file: &File, path: &Path) -> bool {
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(opened), Ok(current)) => opened.dev() == current.dev() && opened.ino() == current.ino(),
+        _ => false
+    }
+}
+
+/// `pid@host` of this process.
+pub fn owner_id() -> String {
+    let mut buffer = [0 as libc::c_char; 256];
+    let host = unsafe {
+        if libc::gethostname(buffer.as_mut_ptr(), buffer.len() as libc::size_t) == 0 {
+            buffer[buffer.len() - 1] = 0;
+            CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned()
+        } else {
+            "unknown".to_string()
+        }
+    };
+    format!("{}@{}", unsafe { libc::getpid() }, host)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use super::{acquire, lock_path, owner_id, LockStatus};
+
+    #[test]
+    fn test_acquire() {
+        let dir = env::temp_dir().join(format!("rpass-test-lock-{}", unsafe { ::libc::getpid() }));
+        fs::create_dir_all(&dir).unwrap();
+        let vault = dir.join("vault.db");
+
+        // Left behind by a crashed process, nobody holds the flock
+        File::create(lock_path(&vault)).unwrap().write_all(b"1@crashed").unwrap();
+
+        let lock = match acquire(&vault).unwrap() {
+            LockStatus::Acquired(lock) => lock,
+            LockStatus::HeldBy(_) => panic!("stale lock must be taken over")
+        };
+        assert_eq!(lock.stale_owner, Some("1@crashed".to_string()));
+
+        // flock locks belong to the open file, so a second open conflicts even in one process
+        match acquire(&vault).unwrap() {
+            LockStatus::HeldBy(owner) => assert_eq!(owner, owner_id()),
+            LockStatus::Acquired(_) => panic!("lock must be exclusive")
+        }
+
+        drop(lock);
+        assert!(!lock_path(&vault).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
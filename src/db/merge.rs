@@ -0,0 +1,210 @@
+//!
+//! Three-way merge of entries, used when the file changed on disk after we opened it.
+//!
+//! `base` is the state we opened, `local` is ours and `remote` is what is on
+//! disk now. Entries are matched by id. An entry changed on one side only takes
+//! that change, an entry changed differently on both sides is kept in both
+//! versions, the remote one renamed and with a new id, and both are flagged as
+//! conflicts for manual resolution.
+//!
+
+use std::collections::BTreeSet;
+use db::Entry;
+use db::entry::new_id;
+use db::trash::TrashedEntry;
+
+pub struct MergeResult {
+    pub entries: Vec<Entry>,
+    /// Titles of the entries in conflict.
+    pub conflicts: Vec<String>
+}
+
+pub fn merge(base: Vec<Entry>, local: Vec<Entry>, remote: Vec<Entry>) -> MergeResult {
+    let mut keys: Vec<String> = Vec::new();
+    {
+        // Local order first, entries new in the remote file at the end
+        let mut seen = BTreeSet::new();
+        for entry in local.iter().chain(remote.iter()).chain(base.iter()) {
+            if seen.insert(entry.id.clone()) {
+                keys.push(entry.id.clone());
+            }
+        }
+    }
+    let titles: Vec<String> = local.iter().chain(remote.iter()).map(|entry| entry.title.clone()).collect();
+
+    let mut base = base;
+    let mut local = local;
+    let mut remote = remote;
+    let mut entries = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for key in keys.iter() {
+        let mut b = take(&mut base, key);
+        let mut l = take(&mut local, key);
+        let mut r = take(&mut remote, key);
+
+        // Using an entry on both sides is not a conflict, the later use wins
+        let last_used = b.iter().chain(l.iter()).chain(r.iter()).map(|entry| entry.last_used).max();
+        for entry in b.iter_mut().chain(l.iter_mut()).chain(r.iter_mut()) {
+            entry.last_used = last_used.unwrap_or(0);
+        }
+
+        if l == r || r == b {
+            // Same on both sides, or changed only locally
+            entries.extend(l);
+        } else if l == b {
+            // Changed only in the remote file, possibly removed there
+            entries.extend(r);
+        } else {
+            match (l, r) {
+                (Some(mut l), Some(mut r)) => {
+                    l.conflict = true;
+                    r.conflict = true;
+                    r.id = new_id();
+                    r.title = unique_title(&format!("{} (conflict)", r.title), &titles, &entries);
+                    conflicts.push(l.title.clone());
+                    conflicts.push(r.title.clone());
+                    entries.push(l);
+                    entries.push(r);
+                },
+                // Removed on one side and changed on the other, keep the change
+                (Some(mut kept), None) | (None, Some(mut kept)) => {
+                    kept.conflict = true;
+                    conflicts.push(kept.title.clone());
+                    entries.push(kept);
+                },
+                (None, None) => ()
+            }
+        }
+    }
+
+    MergeResult { entries, conflicts }
+}
+
+/// Merge the trash sections. An entry stays in the trash if it is there on both
+/// sides or was added to it on one side, entries emptied or restored on one
+/// side are gone. `entries` are the merged entries, which can't be in the trash.
+pub fn merge_trash(base: Vec<TrashedEntry>, local: Vec<TrashedEntry>, remote: Vec<TrashedEntry>, entries: &[Entry])
+    -> Vec<TrashedEntry>
+{
+    let in_base = |id: &str| base.iter().any(|trashed| trashed.entry.id == id);
+    let mut trash: Vec<TrashedEntry> = Vec::new();
+    let mut remote = remote;
+    for trashed in local {
+        let position = remote.iter().position(|other| other.entry.id == trashed.entry.id);
+        let in_remote = position.map(|index| remote.remove(index)).is_some();
+        if in_remote || !in_base(&trashed.entry.id) {
+            trash.push(trashed);
+        }
+    }
+    for trashed in remote {
+        if !in_base(&trashed.entry.id) {
+            trash.push(trashed);
+        }
+    }
+    trash.retain(|trashed| !entries.iter().any(|entry| entry.id == trashed.entry.id));
+    trash
+}
+
+/// Merge the explicitly created groups, a group stays if it is on both sides
+/// or was created on one side.
+pub fn merge_groups(base: BTreeSet<String>, local: BTreeSet<String>, remote: BTreeSet<String>) -> BTreeSet<String> {
+    local.union(&remote)
+        .filter(|path| (local.contains(*path) && remote.contains(*path)) || !base.contains(*path))
+        .cloned()
+        .collect()
+}
+
+fn take(entries: &mut Vec<Entry>, id: &str) -> Option<Entry> {
+    entries.iter().position(|entry| entry.id == id).map(|index| entries.remove(index))
+}
+
+fn unique_title(candidate: &str, titles: &[String], entries: &[Entry]) -> String {
+    let taken = |title: &str| titles.iter().any(|t| t == title) || entries.iter().any(|e| e.title == title);
+    let mut title = candidate.to_string();
+    let mut counter = 2;
+    while taken(&title) {
+        title = format!("{} {}", candidate, counter);
+        counter += 1;
+    }
+    title
+}
+
+#[cfg(test)]
+mod tests {
+    use db::Entry;
+    use db::trash::TrashedEntry;
+    use super::{merge, merge_trash};
+
+    fn entries(items: &[(&str, &str)]) -> Vec<Entry> {
+        // The same title means the same entry on all sides
+        items.iter().map(|&(title, user)| {
+            let mut entry = Entry::new(title, user, "pass");
+            entry.id = format!("id-{}", title);
+            entry
+        }).collect()
+    }
+
+    #[test]
+    fn test_merge_independent_changes() {
+        let base = entries(&[("a", "1"), ("b", "1"), ("c", "1")]);
+        // Local: changed a, removed c, added d
+        let local = entries(&[("a", "2"), ("b", "1"), ("d", "1")]);
+        // Remote: changed b, added e
+        let remote = entries(&[("a", "1"), ("b", "2"), ("c", "1"), ("e", "1")]);
+
+        let result = merge(base, local, remote);
+        assert!(result.conflicts.is_empty());
+        let merged: Vec<(String, String)> = result.entries.iter()
+            .map(|e| (e.title.clone(), e.username().to_string()))
+            .collect();
+        assert_eq!(merged, vec![
+            ("a".to_string(), "2".to_string()),
+            ("b".to_string(), "2".to_string()),
+            ("d".to_string(), "1".to_string()),
+            ("e".to_string(), "1".to_string())
+        ]);
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let base = entries(&[("a", "1")]);
+        let local = entries(&[("a", "local")]);
+        let remote = entries(&[("a", "remote")]);
+
+        let result = merge(base, local, remote);
+        assert_eq!(result.conflicts, vec!["a".to_string(), "a (conflict)".to_string()]);
+        assert_eq!(result.entries.len(), 2);
+        assert!(result.entries.iter().all(|e| e.conflict));
+        assert_eq!(result.entries[1].username(), "remote");
+        assert!(result.entries[0].id != result.entries[1].id);
+    }
+
+    #[test]
+    fn test_merge_last_used() {
+        let base = entries(&[("a", "1")]);
+        let mut local = entries(&[("a", "1")]);
+        let mut remote = entries(&[("a", "1")]);
+        local[0].last_used = 10;
+        remote[0].last_used = 20;
+
+        let result = merge(base, local, remote);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.entries.len(), 1);
+        assert_eq!(result.entries[0].last_used, 20);
+    }
+
+    #[test]
+    fn test_merge_trash() {
+        let trashed = |title: &str| TrashedEntry { entry: entries(&[(title, "1")]).pop().unwrap(), removed: 1 };
+        // Local emptied a and removed c, remote removed d and restored b
+        let base = vec![trashed("a"), trashed("b")];
+        let local = vec![trashed("b"), trashed("c")];
+        let remote = vec![trashed("a"), trashed("d")];
+        let merged = entries(&[("b", "1")]);
+
+        let trash = merge_trash(base, local, remote, &merged);
+        let titles: Vec<&str> = trash.iter().map(|t| &t.entry.title[..]).collect();
+        assert_eq!(titles, vec!["c", "d"]);
+    }
+}
@@ -1,7 +1,20 @@
 #[allow(dead_code)]
 pub use self::entry::Entry;
 pub use self::db::Database;
-pub use self::db::DatabaseInFile;
+pub use self::db::{DatabaseInFile, SaveReport};
+pub use self::error::DbError;
 
 pub mod entry;
+// The database and its file, under the name the rest of the code knows them by
+#[allow(clippy::module_inception)]
 pub mod db;
+pub mod error;
+pub mod backup;
+pub mod settings;
+pub mod atomic;
+pub mod lock;
+pub mod merge;
+pub mod trash;
+pub mod group;
+pub mod format;
+pub mod kdf;
@@ -0,0 +1,134 @@
+//!
+//! Per-vault settings, stored in the encrypted payload together with the entries.
+//!
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+pub const DEFAULT_BACKUP_COUNT: usize = 5;
+pub const DEFAULT_HISTORY_LENGTH: usize = 10;
+pub const DEFAULT_TRASH_RETENTION_DAYS: usize = 30;
+pub const DEFAULT_AUTO_LOCK_MINUTES: usize = 10;
+/// A week, longer is the same as never.
+pub const MAX_AUTO_LOCK_MINUTES: usize = 7 * 24 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Number of backup generations kept, 0 disables backups.
+    #[serde(default="default_backup_count")]
+    pub backup_count: usize,
+    /// Directory for backups, next to the vault file if not set.
+    #[serde(default)]
+    pub backup_dir: Option<String>,
+    /// Number of previous passwords kept for every entry.
+    #[serde(default="default_history_length")]
+    pub history_length: usize,
+    /// Days after which removed entries are deleted for good, 0 keeps them forever.
+    #[serde(default="default_trash_retention_days")]
+    pub trash_retention_days: usize,
+    /// Minutes without input after which the interactive mode locks the database, 0 never locks.
+    #[serde(default="default_auto_lock_minutes")]
+    pub auto_lock_minutes: usize
+}
+
+fn default_backup_count() -> usize {
+    DEFAULT_BACKUP_COUNT
+}
+
+fn default_history_length() -> usize {
+    DEFAULT_HISTORY_LENGTH
+}
+
+fn default_trash_retention_days() -> usize {
+    DEFAULT_TRASH_RETENTION_DAYS
+}
+
+fn default_auto_lock_minutes() -> usize {
+    DEFAULT_AUTO_LOCK_MINUTES
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            backup_count: DEFAULT_BACKUP_COUNT,
+            backup_dir: None,
+            history_length: DEFAULT_HISTORY_LENGTH,
+            trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+            auto_lock_minutes: DEFAULT_AUTO_LOCK_MINUTES
+        }
+    }
+}
+
+impl Settings {
+    /// All settings with their current values, in the form accepted by `set`.
+    pub fn values(&self) -> BTreeMap<&'static str, String> {
+        let mut values = BTreeMap::new();
+        values.insert("backup_count", self.backup_count.to_string());
+        values.insert("backup_dir", self.backup_dir.clone().unwrap_or_default());
+        values.insert("history_length", self.history_length.to_string());
+        values.insert("trash_retention_days", self.trash_retention_days.to_string());
+        values.insert("auto_lock_minutes", self.auto_lock_minutes.to_string());
+        values
+    }
+
+    /// Change a setting from its textual value. An empty value resets optional settings.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "backup_count" => self.backup_count = parse_number(name, value)?,
+            "backup_dir" => self.backup_dir = if value.is_empty() { None } else { Some(value.to_string()) },
+            "history_length" => self.history_length = parse_number(name, value)?,
+            "trash_retention_days" => self.trash_retention_days = parse_number(name, value)?,
+            "auto_lock_minutes" => {
+                let minutes = parse_number(name, value)?;
+                if minutes > MAX_AUTO_LOCK_MINUTES {
+                    return Err(format!("Value of '{}' can be at most {}, 0 never locks.", name, MAX_AUTO_LOCK_MINUTES));
+                }
+                self.auto_lock_minutes = minutes;
+            },
+            _ => return Err(format!("Unknown setting '{}'.", name))
+        }
+        Ok(())
+    }
+
+    /// Time without input after which the interactive mode locks, None never locks.
+    /// A file written elsewhere can hold any number, so it's capped here too.
+    pub fn auto_lock_timeout(&self) -> Option<Duration> {
+        match self.auto_lock_minutes {
+            0 => None,
+            minutes => Some(Duration::from_secs(cmp::min(minutes, MAX_AUTO_LOCK_MINUTES) as u64 * 60))
+        }
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| format!("Value of '{}' has to be a non-negative number.", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{Settings, MAX_AUTO_LOCK_MINUTES};
+
+    #[test]
+    fn test_set() {
+        let mut settings = Settings::default();
+        settings.set("backup_count", "3").unwrap();
+        settings.set("backup_dir", "/tmp/backups").unwrap();
+        assert_eq!(settings.backup_count, 3);
+        assert_eq!(settings.backup_dir, Some("/tmp/backups".to_string()));
+        settings.set("history_length", "0").unwrap();
+        assert_eq!(settings.history_length, 0);
+        settings.set("auto_lock_minutes", "0").unwrap();
+        assert_eq!(settings.auto_lock_minutes, 0);
+        assert_eq!(settings.auto_lock_timeout(), None);
+        assert!(settings.set("auto_lock_minutes", &(MAX_AUTO_LOCK_MINUTES + 1).to_string()).is_err());
+        settings.auto_lock_minutes = usize::MAX;
+        assert_eq!(settings.auto_lock_timeout(), Some(Duration::from_secs(MAX_AUTO_LOCK_MINUTES as u64 * 60)));
+
+        settings.set("backup_dir", "").unwrap();
+        assert_eq!(settings.backup_dir, None);
+        assert!(settings.set("backup_count", "-1").is_err());
+        assert!(settings.set("unknown", "1").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+//!
+//! Removed entries, kept in the encrypted payload until they expire.
+//!
+//! `remove` moves an entry here, so a mistake can be undone with `trash restore`.
+//! Entries older than the retention period of the vault are dropped for good.
+//!
+
+use db::Entry;
+use timestamp::Timestamp;
+
+const SECONDS_PER_DAY: u64 = 86400;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TrashedEntry {
+    pub entry: Entry,
+    /// When the entry was moved to the trash.
+    pub removed: Timestamp
+}
+
+/// Drop entries removed more than `retention_days` ago, 0 keeps them forever.
+/// Returns the number of dropped entries.
+pub fn expire(trash: &mut Vec<TrashedEntry>, retention_days: usize, now: Timestamp) -> usize {
+    if retention_days == 0 {
+        return 0;
+    }
+    let cutoff = now.saturating_sub(retention_days as u64 * SECONDS_PER_DAY);
+    let before = trash.len();
+    trash.retain(|trashed| trashed.removed >= cutoff);
+    before - trash.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use db::Entry;
+    use super::{expire, TrashedEntry};
+
+    #[test]
+    fn test_expire() {
+        let now = 100 * 86400;
+        let mut trash = vec![
+            TrashedEntry { entry: Entry::new("old", "name", "pass"), removed: now - 31 * 86400 },
+            TrashedEntry { entry: Entry::new("new", "name", "pass"), removed: now - 29 * 86400 }
+        ];
+        assert_eq!(expire(&mut trash, 0, now), 0);
+        assert_eq!(expire(&mut trash, 30, now), 1);
+        assert_eq!(trash[0].entry.title, "new");
+    }
+}
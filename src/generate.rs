@@ -0,0 +1,191 @@
+//!
+//! Random password generator.
+//!
+//! Characters are drawn from `OsRng` and written into a buffer allocated
+//! once with the final capacity, which is then moved into a `SecStr`.
+//! So there is no plaintext copy left behind after the generation.
+//!
+
+use std::error;
+use std::fmt;
+use rand::{Rng, OsRng};
+use secstr::SecStr;
+
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"0123456789";
+const SYMBOLS: &[u8] = b"!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+/// Characters easily confused with each other when read by a human.
+const AMBIGUOUS: &[u8] = b"0O1lI|`'\"";
+
+pub const DEFAULT_LENGTH: usize = 20;
+/// Longer passwords are surely a typo, refused before allocating the buffer.
+pub const MAX_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    /// Leave out characters like 0/O or 1/l/I.
+    pub exclude_ambiguous: bool,
+    /// Minimal number of characters from each enabled class.
+    pub min_each: usize
+}
+
+impl PasswordPolicy {
+    pub fn new() -> PasswordPolicy {
+        PasswordPolicy {
+            length: DEFAULT_LENGTH,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            exclude_ambiguous: false,
+            min_each: 1
+        }
+    }
+
+    // Character sets of the enabled classes, without the excluded characters
+    fn classes(&self) -> Vec<Vec<u8>> {
+        let enabled = [
+            (self.lowercase, LOWERCASE),
+            (self.uppercase, UPPERCASE),
+            (self.digits, DIGITS),
+            (self.symbols, SYMBOLS)
+        ];
+        enabled.iter()
+            .filter(|&&(on, _)| on)
+            .map(|&(_, chars)| chars.iter()
+                 .cloned()
+                 .filter(|c| !self.exclude_ambiguous || !AMBIGUOUS.contains(c))
+                 .collect())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenerateError {
+    NoCharacterClass,
+    ZeroLength,
+    TooLong,
+    /// The length is too small to hold `min_each` characters of every class.
+    TooShort { required: usize }
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GenerateError::NoCharacterClass => write!(f, "at least one character class has to be enabled"),
+            GenerateError::ZeroLength => write!(f, "password length has to be positive"),
+            GenerateError::TooLong => write!(f, "password can't be longer than {} characters", MAX_LENGTH),
+            GenerateError::TooShort { required } =>
+                write!(f, "password has to be at least {} characters long to satisfy the policy", required)
+        }
+    }
+}
+
+impl error::Error for GenerateError {
+    fn description(&self) -> &str {
+        "invalid password policy"
+    }
+}
+
+/// Generate a random password satisfying the policy.
+pub fn generate(policy: &PasswordPolicy) -> Result<SecStr, GenerateError> {
+    let classes = policy.classes();
+    if classes.is_empty() {
+        return Err(GenerateError::NoCharacterClass);
+    }
+    if policy.length == 0 {
+        return Err(GenerateError::ZeroLength);
+    }
+    if policy.length > MAX_LENGTH {
+        return Err(GenerateError::TooLong);
+    }
+    let required = match classes.len().checked_mul(policy.min_each) {
+        Some(required) => required,
+        // No length could satisfy it
+        None => return Err(GenerateError::TooShort { required: usize::MAX })
+    };
+    if required > policy.length {
+        return Err(GenerateError::TooShort { required });
+    }
+
+    let all: Vec<u8> = classes.iter().flat_map(|class| class.iter().cloned()).collect();
+    let mut rng = OsRng::new().unwrap();
+
+    // Allocated only once, so no partial copies are left behind by reallocation
+    let mut buffer: Vec<u8> = Vec::with_capacity(policy.length);
+    for class in classes.iter() {
+        for _ in 0..policy.min_each {
+            buffer.push(class[rng.gen_range(0, class.len())]);
+        }
+    }
+    while buffer.len() < policy.length {
+        buffer.push(all[rng.gen_range(0, all.len())]);
+    }
+    // The mandatory characters are at the beginning, mix them in
+    rng.shuffle(&mut buffer);
+
+    // All characters are ASCII, from_utf8 takes over the buffer without copying
+    Ok(SecStr::new(String::from_utf8(buffer).unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate, PasswordPolicy, GenerateError, AMBIGUOUS, DIGITS, SYMBOLS};
+
+    #[test]
+    fn test_length_and_classes() {
+        let mut policy = PasswordPolicy::new();
+        policy.length = 12;
+        policy.min_each = 3;
+
+        for _ in 0..20 {
+            let mut password = generate(&policy).unwrap();
+            password.unlock();
+            let bytes = password.string.as_bytes().to_vec();
+            password.delete();
+
+            assert_eq!(bytes.len(), 12);
+            assert!(bytes.iter().filter(|c| (**c as char).is_lowercase()).count() >= 3);
+            assert!(bytes.iter().filter(|c| (**c as char).is_uppercase()).count() >= 3);
+            assert!(bytes.iter().filter(|c| DIGITS.contains(c)).count() >= 3);
+            assert!(bytes.iter().filter(|c| SYMBOLS.contains(c)).count() >= 3);
+        }
+    }
+
+    #[test]
+    fn test_exclude_ambiguous() {
+        let mut policy = PasswordPolicy::new();
+        policy.length = 200;
+        policy.exclude_ambiguous = true;
+
+        let mut password = generate(&policy).unwrap();
+        password.unlock();
+        assert!(!password.string.bytes().any(|c| AMBIGUOUS.contains(&c)));
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        let mut policy = PasswordPolicy::new();
+        policy.length = 3;
+        assert_eq!(generate(&policy).err(), Some(GenerateError::TooShort { required: 4 }));
+
+        // Used to overflow or abort on the allocation
+        policy.min_each = usize::MAX;
+        assert_eq!(generate(&policy).err(), Some(GenerateError::TooShort { required: usize::MAX }));
+        policy.min_each = 1;
+        policy.length = usize::MAX;
+        assert_eq!(generate(&policy).err(), Some(GenerateError::TooLong));
+
+        policy.lowercase = false;
+        policy.uppercase = false;
+        policy.digits = false;
+        policy.symbols = false;
+        assert_eq!(generate(&policy).err(), Some(GenerateError::NoCharacterClass));
+    }
+}
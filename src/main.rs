@@ -1,24 +1,38 @@
-#![feature(custom_derive, plugin)]
-#![plugin(serde_macros)]
-
 extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate serde_json;
 
 extern crate libc;
 extern crate getopts;
 extern crate rand;
 extern crate crypto;
-extern crate rpassword;
+extern crate argon2;
+extern crate regex;
 
-use getopts::Options;
+use getopts::{Options, Matches, ParsingStyle};
 use std::env;
 use std::io;
 use std::io::Write;
+use std::process;
+use std::time::Duration;
 use db::{DatabaseInFile};
+use commands::error::{CommandError, CommandResult};
+use commands::input::PasswordSource;
+use commands::complete::DbCompleter;
+use readline::Editor;
 
 mod secstr;
+mod timestamp;
+mod generate;
+mod passphrase;
+mod search;
+mod readline;
+mod shellwords;
+mod clipboard;
 mod texts;
 mod commands;
+mod agent;
 mod db;
 mod nacl; // bindings to tweetnacl crypto library
 
@@ -30,10 +44,14 @@ fn main() {
     let args: Vec<String> = env::args().collect();
 
     let mut opts = Options::new();
+    // Options belong to rpass itself, everything after the command goes to the command
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
     opts.optflag("h", "help", "print this help menu");
+    opts.optopt("", "password-fd", "read master password from file descriptor", "FD");
+    opts.optopt("", "password-env", "read master password from environment variable", "VAR");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => { m }
-        Err(f) => { panic!(f.to_string()) }
+        Err(f) => fail(CommandError::InvalidInput(f.to_string()))
     };
 
     if matches.opt_present("h") || matches.free.is_empty(){
@@ -41,55 +59,209 @@ fn main() {
         return;
     }
 
-    let command = matches.free.get(0);
+    readline::catch_interrupt();
+
+    let source = match password_source(&matches) {
+        Ok(source) => source,
+        Err(why) => fail(why)
+    };
+
+    let command: &str = &matches.free[0];
     match command {
-         Some(value) => match value.as_ref() {
-            "create" => {
-                let opt_db = commands::create::call(&matches.free[1..]);
-                if opt_db.is_some(){
-                    println!("Database successfully created.");
-                    command_loop(opt_db.unwrap());
-                }
-            },
-            "open" => {
-                let opt_db = commands::open::call(&matches.free[1..]);
-                if opt_db.is_some(){
-                    println!("Database successfully opened.");
-                    command_loop(opt_db.unwrap());
-                }
-            },
-            _ => {
-                println!("Unknown command '{}'", value);
-                usage()
+        "create" => {
+            let file_db = unwrap_or_fail(commands::create::call(&matches.free[1..]));
+            println!("Database successfully created.");
+            command_loop(file_db);
+        },
+        "open" => {
+            let file_db = unwrap_or_fail(commands::open::call(&matches.free[1..], &source));
+            println!("Database successfully opened.");
+            command_loop(file_db);
+        },
+        "agent" => unwrap_or_fail(commands::agent::call(&matches.free[1..], &source)),
+        "generate" | "passphrase" | "kdf-benchmark" => {
+            let params: Vec<&str> = matches.free[1..].iter().map(|s| s.as_ref()).collect();
+            unwrap_or_fail(match command {
+                "generate" => commands::generate::call(&params),
+                "passphrase" => commands::passphrase::call(&params),
+                _ => commands::kdf::benchmark(&params)
+            });
+        },
+        "list" | "show" | "copy" | "new" | "edit" | "remove" | "passwd" |
+        "backups" | "restore" | "settings" | "stale" | "history" | "restore-password" |
+        "trash" | "mkdir" | "mv" | "rmdir" | "search" => {
+            if matches.free.len() < 2 {
+                fail(CommandError::Usage(texts::USAGE));
             }
+            let params: Vec<&str> = matches.free[2..].iter().map(|s| s.as_ref()).collect();
+            // A running agent saves typing the master password
+            if let Some(res) = commands::agent::run_through_agent(command, &matches.free[1], &params) {
+                unwrap_or_fail(res);
+                return;
+            }
+            let mut file_db = unwrap_or_fail(commands::open::call(&matches.free[1..2], &source));
+            let res = match command {
+                "copy" => commands::copy::call_and_wait(&mut file_db, &params),
+                _ => run_db_command(&mut file_db, command, &params)
+            };
+            // fail() exits without running the destructors
+            file_db.close();
+            unwrap_or_fail(res);
         },
-        None => panic!("No command!"),
+        _ => {
+            println!("Unknown command '{}'", command);
+            usage();
+            process::exit(commands::error::EXIT_USAGE);
+        }
+    }
+}
+
+fn password_source(matches: &Matches) -> CommandResult<PasswordSource> {
+    if let Some(fd) = matches.opt_str("password-fd") {
+        return match fd.parse() {
+            Ok(fd) => Ok(PasswordSource::Fd(fd)),
+            Err(_) => Err(CommandError::InvalidInput(format!("Invalid file descriptor '{}'.", fd)))
+        };
+    }
+    if let Some(var) = matches.opt_str("password-env") {
+        return Ok(PasswordSource::Env(var));
+    }
+    Ok(PasswordSource::Prompt)
+}
+
+fn unwrap_or_fail<T>(res: CommandResult<T>) -> T {
+    match res {
+        Ok(value) => exit_if_interrupted(value),
+        Err(why) => fail(why)
+    }
+}
+
+fn fail(why: CommandError) -> ! {
+    exit_if_interrupted(());
+    let _ = writeln!(io::stderr(), "{}", why);
+    process::exit(why.exit_code());
+}
+
+// The cleanup is done by now, Ctrl-C only decides the exit code
+fn exit_if_interrupted<T>(value: T) -> T {
+    if readline::interrupted() {
+        process::exit(readline::EXIT_INTERRUPTED);
+    }
+    value
+}
+
+/// Run a command working on an opened database, unknown commands print the command list.
+fn run_db_command(file_db: &mut Box<DatabaseInFile>, command: &str, params: &[&str]) -> CommandResult<()> {
+    match command {
+        "list" => commands::list::call(file_db, params),
+        "new" => commands::new::call(file_db, params),
+        "show" => commands::show::call(file_db, params),
+        "copy" => commands::copy::call(file_db, params),
+        "edit" => commands::edit::call(file_db, params),
+        "remove" => commands::remove::call(file_db, params),
+        "passwd" => commands::passwd::call(file_db, params),
+        "backups" => commands::backups::call(file_db, params),
+        "restore" => commands::restore::call(file_db, params),
+        "settings" => commands::settings::call(file_db, params),
+        "stale" => commands::stale::call(file_db, params),
+        "history" => commands::history::call(file_db, params),
+        "restore-password" => commands::restore_password::call(file_db, params),
+        "trash" => commands::trash::call(file_db, params),
+        "mkdir" => commands::mkdir::call(file_db, params),
+        "mv" => commands::mv::call(file_db, params),
+        "rmdir" => commands::rmdir::call(file_db, params),
+        "search" => commands::search::call(file_db, params),
+        "generate" => commands::generate::call(params),
+        "passphrase" => commands::passphrase::call(params),
+        _ => {
+            print_db_commands();
+            Ok(())
+        }
     }
 }
 
 fn command_loop(mut file_db: Box<DatabaseInFile>){
     print_db_commands();
 
+    let mut editor = Editor::new();
     loop {
-        let mut input = String::new();
-        print!("rpass> ");
-        io::stdout().flush().unwrap();
-        let res = io::stdin().read_line(&mut input);
-        if res.is_err(){
-            println!("Error reading input, terminating");
-            return;
+        let timeout = file_db.db.settings.auto_lock_timeout();
+        let res = editor.read_line("rpass> ", &DbCompleter::new(&file_db.db), timeout);
+        let input = match res {
+            Ok(Some(input)) => input,
+            // Ctrl-D
+            Ok(None) => break,
+            Err(ref why) if why.kind() == io::ErrorKind::Interrupted => break,
+            Err(ref why) if why.kind() == io::ErrorKind::TimedOut => {
+                if !lock_inactive(&mut file_db, timeout) {
+                    break;
+                }
+                continue;
+            },
+            Err(_) => {
+                println!("Error reading input, terminating");
+                break;
+            }
+        };
+
+        let words = match shellwords::split(&input) {
+            Ok(words) => words,
+            Err(why) => {
+                println!("{}", CommandError::from(why));
+                continue;
+            }
+        };
+        let words: Vec<&str> = words.iter().map(|word| word.as_ref()).collect();
+        match words.first() {
+            None => continue,
+            Some(&"exit") | Some(&"quit") => break,
+            Some(&"lock") => if !lock(&mut file_db, "Database locked.") {
+                break;
+            },
+            Some(command) => {
+                // The prompts of the command lock the database just like the command line
+                readline::set_prompt_timeout(timeout);
+                let res = run_db_command(&mut file_db, command, &words[1..]);
+                readline::set_prompt_timeout(None);
+                let timed_out = match res {
+                    Err(CommandError::Io(ref why)) if why.kind() == io::ErrorKind::TimedOut => true,
+                    Err(ref why) if !readline::interrupted() => {
+                        println!("{}", why);
+                        false
+                    },
+                    _ => false
+                };
+                if timed_out && !lock_inactive(&mut file_db, timeout) {
+                    break;
+                }
+            }
+        }
+        if readline::interrupted() {
+            break;
         }
+    }
 
-        let words: Vec<&str> = input.split_whitespace().collect();
+    // The clearing threads would die with the process
+    clipboard::clear_pending();
+    file_db.close();
+    println!("Database closed.");
+    exit_if_interrupted(());
+}
 
-        match words[0] {
-            "list" => commands::list::call(&file_db),
-            "new" => commands::new::call(&mut file_db),
-            "show" => commands::show::call(&file_db, &words[1..]),
-            "copy" => commands::copy::call(&file_db, &words[1..]),
-            "edit" => commands::edit::call(&mut file_db, &words[1..]),
-            "remove" => commands::remove::call(&mut file_db, &words[1..]),
-            _ => print_db_commands()
+fn lock_inactive(file_db: &mut Box<DatabaseInFile>, timeout: Option<Duration>) -> bool {
+    let minutes = timeout.map(|timeout| timeout.as_secs() / 60).unwrap_or(0);
+    lock(file_db, &format!("Database locked after {} minutes of inactivity.", minutes))
+}
+
+/// Lock the database until the master password is entered again, false if it wasn't.
+fn lock(file_db: &mut Box<DatabaseInFile>, message: &str) -> bool {
+    match commands::lock::call(file_db, message) {
+        Ok(()) => true,
+        Err(why) => {
+            if !readline::interrupted() {
+                println!("{}", why);
+            }
+            false
         }
     }
 }
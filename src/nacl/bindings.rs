@@ -5,7 +5,7 @@ use self::libc::*;
 // MIT license, by Erik Price
 
 //#[link(name = "tweetnacl", kind="static")]
-extern {
+extern "C" {
 	/*
 	#include "crypto_secretbox.h"
 
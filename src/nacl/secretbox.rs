@@ -23,8 +23,8 @@
 // Inspired by https://github.com/erik/knuckle/blob/master/src/secretbox.rs
 // MIT license, by Erik Price
 
-use std::iter::repeat;
-use std::ptr::copy_nonoverlapping;
+use std::iter::repeat_n;
+use std::ptr::{self, copy_nonoverlapping};
 use rand::{ Rng, OsRng };
 use nacl::bindings::{crypto_secretbox, crypto_secretbox_open};
 
@@ -61,7 +61,7 @@ impl SecretMsg {
 
         unsafe { copy_nonoverlapping(bytes.as_ptr(), nonce.as_mut_ptr(), NONCE_BYTES); }
 
-        Some(SecretMsg { nonce: nonce, cipher: cipher.to_vec() })
+        Some(SecretMsg { nonce, cipher: cipher.to_vec() })
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
@@ -107,7 +107,7 @@ impl SecretKey {
         let &SecretKey(sk) = self;
 
         unsafe {
-            let mut cipher: Vec<u8> = repeat(0u8).take(stretched.len()).collect();
+            let mut cipher: Vec<u8> = repeat_n(0u8, stretched.len()).collect();
 
             // generate Nonce from os source or rand (e.g. linux /dev/urandom)
             // TODO verify we have enough entropy
@@ -120,9 +120,12 @@ impl SecretKey {
                                    stretched.len() as u64,
                                    nonce.as_ptr(),
                                    sk.as_ptr()) {
-                0 => SecretMsg {
-                    nonce: nonce,
-                    cipher: cipher
+                0 => {
+                    wipe(&mut stretched);
+                    SecretMsg {
+                        nonce,
+                        cipher
+                    }
                 },
                 _ => panic!("crypto_secretbox failed")
             }
@@ -133,7 +136,7 @@ impl SecretKey {
     /// plain text.
     pub fn decrypt(&self, msg: &SecretMsg) -> Result<Vec<u8>, SecretBoxError> {
         let &SecretKey(sk) = self;
-        let mut plaintext: Vec<u8> = repeat(0u8).take(msg.cipher.len()).collect();
+        let mut plaintext: Vec<u8> = repeat_n(0u8, msg.cipher.len()).collect();
 
         unsafe {
             match crypto_secretbox_open(plaintext.as_mut_ptr(),
@@ -141,26 +144,37 @@ impl SecretKey {
                                         msg.cipher.len() as u64,
                                         msg.nonce.as_ptr(),
                                         sk.as_ptr()) {
-                0 => Ok((&plaintext[ZERO_BYTES .. plaintext.len()]).to_vec()),
+                0 => {
+                    let res = plaintext[ZERO_BYTES .. plaintext.len()].to_vec();
+                    wipe(&mut plaintext);
+                    Ok(res)
+                },
                 -1 => Err(SecretBoxError::VerificationFail),
-                res => panic!(format!("crypto_secretbox_open failed, reason {}", res))
+                res => panic!("crypto_secretbox_open failed, reason {}", res)
             }
         }
     }
 }
 
+// Zero the padded plaintext buffers, volatile so the writes aren't optimized away
+fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
 
 #[test]
 fn test_secretbox_sanity() {
     for i in 0..16 {
-        let msg: Vec<u8> = repeat(i as u8).take(i * 4).collect();
+        let msg: Vec<u8> = repeat_n(i as u8, i * 4).collect();
 
         let key = SecretKey::from_str("some secret key");
         let SecretMsg { nonce, cipher } = key.encrypt(&msg);
 
         println!("enc:\t{:?}\nnonce:\t{:?}", cipher, nonce.to_vec());
 
-        let decr_opt = key.decrypt(&SecretMsg { nonce: nonce, cipher: cipher });
+        let decr_opt = key.decrypt(&SecretMsg { nonce, cipher });
 
         assert!(decr_opt.is_ok());
 
@@ -173,7 +187,7 @@ fn test_secretbox_sanity() {
 
 #[test]
 fn test_secretbox_uniqueness() {
-    let msg: Vec<u8> = repeat(0x53u8).take(128).collect();
+    let msg: Vec<u8> = repeat_n(0x53u8, 128).collect();
 
     let key1 = SecretKey::from_str("1");
     let key2 = SecretKey::from_str("");
@@ -190,7 +204,7 @@ fn test_secretbox_uniqueness() {
 #[test]
 fn test_secretbox_mac_sanity() {
 
-    let msg: Vec<u8> = repeat(0xff).take(0xff).collect();
+    let msg: Vec<u8> = repeat_n(0xff, 0xff).collect();
 
     let key = SecretKey::from_str("some secret key");
 
@@ -206,7 +220,7 @@ fn test_secretbox_mac_sanity() {
     ciphers[2].push(last + 1);
 
     for c in ciphers.iter() {
-        let decr = key.decrypt(&SecretMsg { nonce: nonce, cipher: c.clone() });
+        let decr = key.decrypt(&SecretMsg { nonce, cipher: c.clone() });
 
         println!("cipher:\t{:?}\ndecr:\t{:?}", c, decr);
         assert!(decr.is_err());
@@ -240,7 +254,7 @@ fn test_secretkey_tamper_resistance() {
 
     // Start past the end of the nonce padding
     for i in 16..tampered_msg.len() {
-        tampered_msg[i] = tampered_msg[i] ^ 0xFF;
+        tampered_msg[i] ^= 0xFF;
 
         let tampered = SecretMsg { nonce: encr.nonce, cipher: tampered_msg.clone() };
         let plaintext = key.decrypt(&tampered);
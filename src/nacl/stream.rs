@@ -1,5 +1,5 @@
 use nacl::bindings::crypto_stream_xor;
-use std::iter::repeat;
+use std::iter::repeat_n;
 
 pub const KEY_BYTES: usize = 32;
 pub const NONCE_BYTES: usize = 24;
@@ -10,7 +10,7 @@ pub fn stream_encrypt_xor(input: &[u8], nonce: &[u8], key: &[u8]) -> Vec<u8> {
         panic!("key or nonce have bad length");
     }
 
-    let mut output: Vec<u8> = repeat(0u8).take(input.len()).collect();
+    let mut output: Vec<u8> = repeat_n(0u8, input.len()).collect();
 
     unsafe {
         // todo check the c call returns null
@@ -21,5 +21,5 @@ pub fn stream_encrypt_xor(input: &[u8], nonce: &[u8], key: &[u8]) -> Vec<u8> {
             key.as_ptr());
     }
 
-    return output;
+    output
 }
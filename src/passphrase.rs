@@ -0,0 +1,214 @@
+//!
+//! Diceware-style passphrase generator.
+//!
+//! Words are picked uniformly with `OsRng` from the embedded wordlist or from
+//! a user supplied file. Like in the `generate` module the passphrase is built
+//! in a buffer of the exact final size and moved into a `SecStr`.
+//!
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::ptr;
+use rand::{Rng, OsRng};
+use secstr::SecStr;
+
+static EMBEDDED_WORDLIST: &str = include_str!("../lib/wordlists/english.txt");
+
+pub const DEFAULT_WORDS: usize = 6;
+/// Longer passphrases are surely a typo, refused before allocating the buffer.
+pub const MAX_WORDS: usize = 64;
+pub const DEFAULT_SEPARATOR: &str = "-";
+const DIGITS: &[u8] = b"0123456789";
+
+pub struct Wordlist {
+    words: Vec<String>
+}
+
+impl Wordlist {
+    /// The built-in English list of BIP-39 (2048 words, 11 bits per word), used
+    /// instead of the EFF list for its shorter words with unique 4-letter prefixes.
+    pub fn embedded() -> Wordlist {
+        Wordlist::parse(EMBEDDED_WORDLIST)
+    }
+
+    /// Load a wordlist with one word per line. Diceware lists with the dice
+    /// numbers in front of the word ("11111 abacus") are accepted as well.
+    pub fn from_file(path: &Path) -> io::Result<Wordlist> {
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+        Ok(Wordlist::parse(&content))
+    }
+
+    fn parse(content: &str) -> Wordlist {
+        let mut words: Vec<String> = content.lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .map(|word| word.to_string())
+            .collect();
+        // Duplicates would make the entropy estimate a lie
+        words.sort();
+        words.dedup();
+        Wordlist { words }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PassphrasePolicy {
+    pub words: usize,
+    pub separator: String,
+    /// Upper-case the first letter of every word.
+    pub capitalize: bool,
+    /// Append a random digit to a randomly chosen word.
+    pub inject_digit: bool
+}
+
+impl PassphrasePolicy {
+    pub fn new() -> PassphrasePolicy {
+        PassphrasePolicy {
+            words: DEFAULT_WORDS,
+            separator: DEFAULT_SEPARATOR.to_string(),
+            capitalize: false,
+            inject_digit: false
+        }
+    }
+
+    /// Entropy in bits of a passphrase generated with this policy from the wordlist.
+    /// Capitalization is deterministic, so it doesn't add anything.
+    pub fn entropy(&self, wordlist: &Wordlist) -> f64 {
+        let mut bits = self.words as f64 * (wordlist.len() as f64).log2();
+        if self.inject_digit {
+            bits += (DIGITS.len() as f64 * self.words as f64).log2();
+        }
+        bits
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassphraseError {
+    NoWords,
+    TooManyWords,
+    /// A wordlist needs at least two distinct words to carry any entropy.
+    WordlistTooSmall
+}
+
+impl fmt::Display for PassphraseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PassphraseError::NoWords => write!(f, "passphrase has to contain at least one word"),
+            PassphraseError::TooManyWords => write!(f, "passphrase can't have more than {} words", MAX_WORDS),
+            PassphraseError::WordlistTooSmall => write!(f, "wordlist has to contain at least two distinct words")
+        }
+    }
+}
+
+impl error::Error for PassphraseError {
+    fn description(&self) -> &str {
+        "invalid passphrase policy"
+    }
+}
+
+pub struct Passphrase {
+    pub secret: SecStr,
+    /// Estimated entropy in bits.
+    pub entropy: f64
+}
+
+pub fn generate(policy: &PassphrasePolicy, wordlist: &Wordlist) -> Result<Passphrase, PassphraseError> {
+    if policy.words == 0 {
+        return Err(PassphraseError::NoWords);
+    }
+    if policy.words > MAX_WORDS {
+        return Err(PassphraseError::TooManyWords);
+    }
+    if wordlist.len() < 2 {
+        return Err(PassphraseError::WordlistTooSmall);
+    }
+
+    let mut rng = OsRng::new().unwrap();
+    let mut picked: Vec<usize> = (0..policy.words).map(|_| rng.gen_range(0, wordlist.len())).collect();
+    let digit = if policy.inject_digit {
+        Some((rng.gen_range(0, policy.words), DIGITS[rng.gen_range(0, DIGITS.len())]))
+    } else {
+        None
+    };
+
+    let size = picked.iter().fold(0, |sum, &i| sum + wordlist.words[i].len())
+        + policy.separator.len() * (policy.words - 1)
+        + if digit.is_some() { 1 } else { 0 };
+    let mut buffer: Vec<u8> = Vec::with_capacity(size);
+
+    for (position, &index) in picked.iter().enumerate() {
+        if position > 0 {
+            buffer.extend(policy.separator.bytes());
+        }
+        let start = buffer.len();
+        buffer.extend(wordlist.words[index].bytes());
+        // Only ASCII letters are changed, so the length stays the same
+        if policy.capitalize {
+            buffer[start] = buffer[start].to_ascii_uppercase();
+        }
+        if let Some((word, value)) = digit {
+            if word == position {
+                buffer.push(value);
+            }
+        }
+    }
+
+    // The picked indices identify the passphrase as well
+    unsafe { ptr::write_bytes(picked.as_mut_ptr(), 0, picked.len()); }
+
+    Ok(Passphrase {
+        secret: SecStr::new(String::from_utf8(buffer).unwrap()),
+        entropy: policy.entropy(wordlist)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate, Wordlist, PassphrasePolicy, PassphraseError};
+
+    #[test]
+    fn test_embedded_wordlist() {
+        let wordlist = Wordlist::embedded();
+        assert_eq!(wordlist.len(), 2048);
+        assert_eq!(PassphrasePolicy::new().entropy(&wordlist), 66.0);
+    }
+
+    #[test]
+    fn test_generate() {
+        let wordlist = Wordlist::parse("11111 alpha\n11112 beta\n11113 gamma\n");
+        let mut policy = PassphrasePolicy::new();
+        policy.words = 4;
+        policy.separator = " ".to_string();
+        policy.capitalize = true;
+        policy.inject_digit = true;
+
+        let mut passphrase = generate(&policy, &wordlist).unwrap();
+        passphrase.secret.unlock();
+        let words: Vec<String> = passphrase.secret.string.split(' ').map(|w| w.to_string()).collect();
+        passphrase.secret.delete();
+
+        assert_eq!(words.len(), 4);
+        assert!(words.iter().all(|w| w.chars().next().unwrap().is_uppercase()));
+        assert_eq!(words.iter().filter(|w| w.chars().last().unwrap().is_ascii_digit()).count(), 1);
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        let wordlist = Wordlist::parse("only\nonly\n");
+        assert_eq!(generate(&PassphrasePolicy::new(), &wordlist).err(), Some(PassphraseError::WordlistTooSmall));
+
+        let mut policy = PassphrasePolicy::new();
+        policy.words = 0;
+        assert_eq!(generate(&policy, &Wordlist::embedded()).err(), Some(PassphraseError::NoWords));
+        // Used to overflow or abort on the allocation
+        policy.words = usize::MAX;
+        assert_eq!(generate(&policy, &Wordlist::embedded()).err(), Some(PassphraseError::TooManyWords));
+    }
+}
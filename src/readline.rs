@@ -0,0 +1,610 @@
+//!
+//! Line editor of the interactive mode.
+//!
+//! The terminal is switched to raw mode while a line is edited, so that the
+//! arrow keys move in the line and through the history, and TAB completes the
+//! word before the cursor. The history lives only as long as the session, it is
+//! never written to disk because command lines can contain secrets.
+//! When stdin is not a terminal, lines are read as they are.
+//!
+
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use libc;
+use shellwords;
+
+/// Source of the candidates for TAB completion.
+pub trait Completer {
+    /// Candidates for `word`, the word before the cursor without its quotes.
+    /// `line` is the text before that word, so the completer can tell which word it is.
+    fn complete(&self, line: &str, word: &str) -> Vec<String>;
+}
+
+pub struct Editor {
+    history: History
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        Editor { history: History::new() }
+    }
+
+    /// Read one line, None at the end of input (Ctrl-D on an empty line).
+    /// Fails with `ErrorKind::TimedOut` when no key is pressed for `timeout`,
+    /// which applies only at a terminal.
+    pub fn read_line(&mut self, prompt: &str, completer: &dyn Completer, timeout: Option<Duration>)
+            -> io::Result<Option<String>> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+            return read_plain(prompt);
+        }
+
+        let result = {
+            let _raw = TerminalMode::raw()?;
+            self.edit(prompt, completer, timeout)
+        };
+        println!();
+        if let Ok(Some(ref line)) = result {
+            self.history.add(line);
+        }
+        result
+    }
+
+    fn edit(&mut self, prompt: &str, completer: &dyn Completer, timeout: Option<Duration>)
+            -> io::Result<Option<String>> {
+        let mut stdin = RawStdin;
+        let mut stdout = io::stdout();
+        let mut line = LineState::new();
+        self.history.reset();
+        refresh(&mut stdout, prompt, &line)?;
+
+        loop {
+            wait_for_input(timeout)?;
+            let key = match read_key(&mut stdin)? {
+                Some(key) => key,
+                None => return Ok(None)
+            };
+            match key {
+                Key::Enter => return Ok(Some(line.text())),
+                Key::CtrlD => {
+                    if line.is_empty() {
+                        return Ok(None);
+                    }
+                    line.delete();
+                },
+                // Cancel the line, like a shell does
+                Key::CtrlC => {
+                    write!(stdout, "^C")?;
+                    return Ok(Some(String::new()));
+                },
+                Key::Char(c) => line.insert(c),
+                Key::Backspace => line.backspace(),
+                Key::Delete => line.delete(),
+                Key::Left => line.left(),
+                Key::Right => line.right(),
+                Key::Home => line.cursor = 0,
+                Key::End => line.cursor = line.chars.len(),
+                Key::CtrlU => line.set(""),
+                Key::Up => if let Some(previous) = self.history.previous(&line.text()) {
+                    line.set(&previous);
+                },
+                Key::Down => if let Some(next) = self.history.next() {
+                    line.set(&next);
+                },
+                Key::Tab => {
+                    let before_word: String = line.chars[..line.word_start()].iter().cloned().collect();
+                    let candidates = completer.complete(&before_word, &line.word());
+                    if !line.complete(&candidates) {
+                        if candidates.is_empty() {
+                            write!(stdout, "\x07")?;
+                        } else {
+                            write!(stdout, "\r\n{}\r\n", candidates.join("  "))?;
+                        }
+                    }
+                },
+                Key::Other => ()
+            }
+            refresh(&mut stdout, prompt, &line)?;
+        }
+    }
+}
+
+/// Exit code after Ctrl-C outside of the line editor, as in shells.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+// Terminal settings at the start, restored when interrupted
+static mut ORIGINAL_TERMIOS: Option<libc::termios> = None;
+// The handler writes to it to wake up `wait_for_input`
+static mut INTERRUPT_PIPE: [libc::c_int; 2] = [-1, -1];
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// See `set_prompt_timeout`
+static PROMPT_TIMEOUT: Mutex<Option<Duration>> = Mutex::new(None);
+
+/// Make the prompts of the commands (`read_password` and the ones waiting with
+/// `prompt_timeout`) fail after this long without input, as the line editor
+/// does with its timeout.
+pub fn set_prompt_timeout(timeout: Option<Duration>) {
+    *PROMPT_TIMEOUT.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = timeout;
+}
+
+/// The timeout set by `set_prompt_timeout`, none at first.
+pub fn prompt_timeout() -> Option<Duration> {
+    *PROMPT_TIMEOUT.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Make Ctrl-C restore the terminal and fail the prompt waiting for input, so
+/// that the session ends through its usual cleanup: clearing the clipboard,
+/// wiping the secrets and removing the lock file. Otherwise Ctrl-C at a password
+/// prompt would leave the terminal without echo.
+pub fn catch_interrupt() {
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) == 0 {
+            return;
+        }
+        let mut original: libc::termios = mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+            return;
+        }
+        // Non-blocking, the handler must never wait for a full pipe
+        let mut fds = [-1; 2];
+        if libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) != 0 {
+            return;
+        }
+        INTERRUPT_PIPE = fds;
+        ORIGINAL_TERMIOS = Some(original);
+        libc::signal(libc::SIGINT, on_interrupt as *const () as libc::sighandler_t);
+    }
+}
+
+/// True once Ctrl-C was pressed, the session should end.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// Only async-signal-safe calls in here
+extern "C" fn on_interrupt(_: libc::c_int) {
+    unsafe {
+        if let Some(ref original) = ORIGINAL_TERMIOS {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+        }
+        libc::write(libc::STDOUT_FILENO, b"\n".as_ptr() as *const libc::c_void, 1);
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        libc::write(INTERRUPT_PIPE[1], b"!".as_ptr() as *const libc::c_void, 1);
+    }
+}
+
+/// Wait until the terminal has input: a whole line, or a key in the line editor.
+/// Fails with `ErrorKind::TimedOut` when nothing comes within `timeout` and with
+/// `ErrorKind::Interrupted` after Ctrl-C. Returns at once when stdin is not a terminal.
+pub fn wait_for_input(timeout: Option<Duration>) -> io::Result<()> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+        return Ok(());
+    }
+    let millis = match timeout {
+        Some(timeout) => {
+            let millis = timeout.as_secs() * 1000 + timeout.subsec_millis() as u64;
+            cmp::min(millis, libc::c_int::MAX as u64) as libc::c_int
+        },
+        None => -1
+    };
+    // A negative fd (no handler installed) is skipped by poll
+    let mut fds = [libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 },
+                   libc::pollfd { fd: unsafe { INTERRUPT_PIPE[0] }, events: libc::POLLIN, revents: 0 }];
+    loop {
+        if interrupted() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+        }
+        match unsafe { libc::poll(fds.as_mut_ptr(), 2, millis) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            },
+            0 => return Err(io::Error::new(io::ErrorKind::TimedOut, "no input")),
+            _ => if fds[1].revents == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Read a line at the terminal without echo, for passwords. Waits like
+/// `wait_for_input` with `prompt_timeout`.
+pub fn read_password() -> io::Result<String> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+        return read_plain("").map(|line| line.unwrap_or_default());
+    }
+    let _no_echo = TerminalMode::no_echo()?;
+    wait_for_input(prompt_timeout())?;
+    let mut password = String::new();
+    io::stdin().read_line(&mut password)?;
+    let length = password.trim_end_matches(['\n', '\r']).len();
+    password.truncate(length);
+    Ok(password)
+}
+
+fn read_plain(prompt: &str) -> io::Result<Option<String>> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(input.trim_end_matches(['\n', '\r']).to_string()))
+}
+
+fn refresh(out: &mut dyn Write, prompt: &str, line: &LineState) -> io::Result<()> {
+    write!(out, "\r{}{}\x1b[K", prompt, line.text())?;
+    let back = line.chars.len() - line.cursor;
+    if back > 0 {
+        write!(out, "\x1b[{}D", back)?;
+    }
+    out.flush()
+}
+
+/// Terminal settings changed for a prompt, restored on drop.
+struct TerminalMode {
+    original: libc::termios
+}
+
+impl TerminalMode {
+    // No echo, no line buffering, Ctrl-C and Ctrl-D come as plain bytes
+    fn raw() -> io::Result<TerminalMode> {
+        TerminalMode::change(|mode| {
+            mode.c_lflag &= !(libc::ECHO | libc::ICANON | libc::ISIG | libc::IEXTEN);
+            mode.c_iflag &= !(libc::IXON | libc::ICRNL);
+            mode.c_cc[libc::VMIN] = 1;
+            mode.c_cc[libc::VTIME] = 0;
+        })
+    }
+
+    // Whole lines as usual, only the newline is echoed
+    fn no_echo() -> io::Result<TerminalMode> {
+        TerminalMode::change(|mode| {
+            mode.c_lflag &= !libc::ECHO;
+            mode.c_lflag |= libc::ECHONL;
+        })
+    }
+
+    fn change<F: FnOnce(&mut libc::termios)>(change: F) -> io::Result<TerminalMode> {
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut mode = original;
+        change(&mut mode);
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &mode) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(TerminalMode { original })
+    }
+}
+
+impl Drop for TerminalMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original); }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    CtrlC,
+    CtrlD,
+    CtrlU,
+    Other
+}
+
+// Stdin without the buffer of io::stdin(), bytes waiting in it would be
+// invisible to `wait_for_input`
+struct RawStdin;
+
+impl Read for RawStdin {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } {
+            -1 => Err(io::Error::last_os_error()),
+            count => Ok(count as usize)
+        }
+    }
+}
+
+fn read_byte(input: &mut dyn Read) -> io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0]))
+    }
+}
+
+fn read_key(input: &mut dyn Read) -> io::Result<Option<Key>> {
+    let byte = match read_byte(input)? {
+        Some(byte) => byte,
+        None => return Ok(None)
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x05 => Key::End,
+        0x03 => Key::CtrlC,
+        0x04 => Key::CtrlD,
+        0x15 => Key::CtrlU,
+        0x1b => read_escape(input)?,
+        byte if byte < 0x20 => Key::Other,
+        byte if byte < 0x80 => Key::Char(byte as char),
+        byte => read_utf8(input, byte)?
+    };
+    Ok(Some(key))
+}
+
+// ESC [ A or ESC O H style sequences of the special keys
+fn read_escape(input: &mut dyn Read) -> io::Result<Key> {
+    let kind = read_byte(input)?;
+    let code = read_byte(input)?;
+    Ok(match (kind, code) {
+        (Some(b'['), Some(b'A')) => Key::Up,
+        (Some(b'['), Some(b'B')) => Key::Down,
+        (Some(b'['), Some(b'C')) => Key::Right,
+        (Some(b'['), Some(b'D')) => Key::Left,
+        (Some(b'['), Some(b'H')) | (Some(b'O'), Some(b'H')) => Key::Home,
+        (Some(b'['), Some(b'F')) | (Some(b'O'), Some(b'F')) => Key::End,
+        (Some(b'['), Some(digit)) if digit.is_ascii_digit() => {
+            // ESC [ n ~
+            if read_byte(input)? != Some(b'~') {
+                return Ok(Key::Other);
+            }
+            match digit {
+                b'1' | b'7' => Key::Home,
+                b'4' | b'8' => Key::End,
+                b'3' => Key::Delete,
+                _ => Key::Other
+            }
+        },
+        _ => Key::Other
+    })
+}
+
+fn read_utf8(input: &mut dyn Read, first: u8) -> io::Result<Key> {
+    let length = if first >= 0xf0 { 4 } else if first >= 0xe0 { 3 } else { 2 };
+    let mut bytes = vec![first];
+    for _ in 1..length {
+        match read_byte(input)? {
+            Some(byte) => bytes.push(byte),
+            None => return Ok(Key::Other)
+        }
+    }
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => text.chars().next().map(Key::Char).unwrap_or(Key::Other),
+        Err(_) => Key::Other
+    })
+}
+
+/// The edited line and the cursor position, in characters.
+struct LineState {
+    chars: Vec<char>,
+    cursor: usize
+}
+
+impl LineState {
+    fn new() -> LineState {
+        LineState { chars: Vec::new(), cursor: 0 }
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().cloned().collect()
+    }
+
+    fn before_cursor(&self) -> String {
+        self.chars[..self.cursor].iter().cloned().collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    fn insert(&mut self, c: char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn left(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+        }
+    }
+
+    fn right(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.cursor += 1;
+        }
+    }
+
+    /// Index of the first character of the word before the cursor, quotes included.
+    fn word_start(&self) -> usize {
+        let before = self.before_cursor();
+        let (offset, _) = shellwords::last_word(&before);
+        before[..offset].chars().count()
+    }
+
+    /// The word before the cursor, the one being completed, without quotes.
+    fn word(&self) -> String {
+        shellwords::last_word(&self.before_cursor()).1
+    }
+
+    /// Replace the word before the cursor with the single candidate, or with the
+    /// longest common prefix of the candidates, quoted if needed. Returns false
+    /// if that wouldn't change anything.
+    fn complete(&mut self, candidates: &[String]) -> bool {
+        let word = self.word();
+        let replacement = match candidates.len() {
+            0 => return false,
+            // A group is followed by more of the path, so its quote stays open
+            1 if candidates[0].ends_with('/') => shellwords::quote(&candidates[0], false),
+            // A finished word is followed by a space
+            1 => format!("{} ", shellwords::quote(&candidates[0], true)),
+            _ => {
+                let prefix = common_prefix(candidates);
+                if prefix.chars().count() <= word.chars().count() || !prefix.to_ascii_lowercase().starts_with(&word.to_ascii_lowercase()) {
+                    return false;
+                }
+                shellwords::quote(&prefix, false)
+            }
+        };
+        let start = self.word_start();
+        let current: String = self.chars[start..self.cursor].iter().cloned().collect();
+        if current == replacement {
+            return false;
+        }
+        let rest: Vec<char> = self.chars[self.cursor..].to_vec();
+        self.chars.truncate(start);
+        self.chars.extend(replacement.chars());
+        self.cursor = self.chars.len();
+        self.chars.extend(rest);
+        true
+    }
+}
+
+fn common_prefix(candidates: &[String]) -> String {
+    let first: Vec<char> = candidates[0].chars().collect();
+    let mut length = first.len();
+    for candidate in candidates[1..].iter() {
+        length = candidate.chars().zip(first.iter()).take(length).take_while(|&(a, b)| a == *b).count();
+    }
+    first[..length].iter().cloned().collect()
+}
+
+/// Lines entered in this session, navigated with the up and down keys.
+struct History {
+    lines: Vec<String>,
+    /// Index of the line shown, `lines.len()` when editing a new one.
+    position: usize,
+    /// The new line, kept while browsing the history.
+    pending: String
+}
+
+impl History {
+    fn new() -> History {
+        History { lines: Vec::new(), position: 0, pending: String::new() }
+    }
+
+    fn reset(&mut self) {
+        self.position = self.lines.len();
+        self.pending.clear();
+    }
+
+    fn add(&mut self, line: &str) {
+        if line.trim().is_empty() || self.lines.last().map(|last| last == line).unwrap_or(false) {
+            return;
+        }
+        self.lines.push(line.to_string());
+    }
+
+    fn previous(&mut self, current: &str) -> Option<String> {
+        if self.position == 0 {
+            return None;
+        }
+        if self.position == self.lines.len() {
+            self.pending = current.to_string();
+        }
+        self.position -= 1;
+        Some(self.lines[self.position].clone())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        if self.position >= self.lines.len() {
+            return None;
+        }
+        self.position += 1;
+        Some(if self.position == self.lines.len() {
+            self.pending.clone()
+        } else {
+            self.lines[self.position].clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::{read_key, Key, LineState, History};
+
+    #[test]
+    fn test_read_key() {
+        let mut input = Cursor::new(b"a\x1b[A\x1b[3~\xc3\xa1\r".to_vec());
+        let mut keys = Vec::new();
+        while let Some(key) = read_key(&mut input).unwrap() {
+            keys.push(key);
+        }
+        assert_eq!(keys, vec![Key::Char('a'), Key::Up, Key::Delete, Key::Char('á'), Key::Enter]);
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut line = LineState::new();
+        line.set("show gi");
+        assert!(line.complete(&["github".to_string()]));
+        assert_eq!(line.text(), "show github ");
+
+        line.set("show g");
+        assert!(line.complete(&["gitlab".to_string(), "github".to_string()]));
+        assert_eq!(line.text(), "show git");
+        // No common prefix to add, the candidates are listed instead
+        assert!(!line.complete(&["gitlab".to_string(), "github".to_string()]));
+
+        line.set("show My");
+        assert!(line.complete(&["My Bank".to_string(), "My Bar".to_string()]));
+        assert_eq!(line.text(), "show \"My Ba");
+        line.insert('n');
+        assert!(line.complete(&["My Bank".to_string()]));
+        assert_eq!(line.text(), "show \"My Bank\" ");
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new();
+        history.add("list");
+        history.add("show github");
+        history.add("show github");
+        history.reset();
+
+        assert_eq!(history.previous("sh"), Some("show github".to_string()));
+        assert_eq!(history.previous(""), Some("list".to_string()));
+        assert_eq!(history.previous(""), None);
+        assert_eq!(history.next(), Some("show github".to_string()));
+        assert_eq!(history.next(), Some("sh".to_string()));
+        assert_eq!(history.next(), None);
+    }
+}
@@ -0,0 +1,325 @@
+//!
+//! Searching entries.
+//!
+//! A query is a list of terms which all have to match. A term either matches
+//! any of the searchable fields, or only one of them when written as
+//! `field:value` (e.g. `user:alice tag:prod`). Values are case-insensitive
+//! substrings, or regular expressions when the query is built with `regex`.
+//! Secrets (the password and secret custom fields) are searched only when
+//! the query allows it, and their plaintext is wiped right after matching.
+//!
+
+use std::cmp;
+use std::error;
+use std::fmt;
+use regex::Regex;
+use db::Entry;
+
+/// Fields a term can be scoped to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Title,
+    Username,
+    Url,
+    Tag,
+    Notes,
+    Group,
+    /// The password and secret custom fields.
+    Secret
+}
+
+impl Field {
+    pub fn parse(name: &str) -> Option<Field> {
+        match name {
+            "title" => Some(Field::Title),
+            "user" | "username" => Some(Field::Username),
+            "url" => Some(Field::Url),
+            "tag" | "tags" => Some(Field::Tag),
+            "notes" => Some(Field::Notes),
+            "group" => Some(Field::Group),
+            "password" | "secret" => Some(Field::Secret),
+            _ => None
+        }
+    }
+
+    // Matches in more specific fields rank higher
+    fn weight(&self) -> u32 {
+        match *self {
+            Field::Title => 8,
+            Field::Username | Field::Tag => 4,
+            Field::Group | Field::Url => 2,
+            Field::Notes | Field::Secret => 1
+        }
+    }
+}
+
+const ALL_FIELDS: [Field; 7] = [Field::Title, Field::Username, Field::Tag, Field::Group,
+                                Field::Url, Field::Notes, Field::Secret];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchError {
+    EmptyQuery,
+    UnknownField(String),
+    InvalidRegex(String),
+    /// The query asks for secrets, but they are not allowed.
+    SecretsNotIncluded
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SearchError::EmptyQuery => write!(f, "nothing to search for"),
+            SearchError::UnknownField(ref name) => write!(f, "unknown field '{}'", name),
+            SearchError::InvalidRegex(ref why) => write!(f, "invalid regular expression: {}", why),
+            SearchError::SecretsNotIncluded => write!(f, "searching passwords requires --include-secrets")
+        }
+    }
+}
+
+impl error::Error for SearchError {
+    fn description(&self) -> &str {
+        "invalid search query"
+    }
+}
+
+enum Matcher {
+    /// Needle in lowercase, compared ignoring case like the `(?i)` of regex.
+    Substring(String),
+    Regex(Regex)
+}
+
+// How well a value matched, better matches rank higher
+const EXACT: u32 = 4;
+const PREFIX: u32 = 2;
+const CONTAINS: u32 = 1;
+
+impl Matcher {
+    fn quality(&self, value: &str) -> Option<u32> {
+        match *self {
+            Matcher::Substring(ref needle) => {
+                if lowercase(value).eq(needle.chars()) {
+                    Some(EXACT)
+                } else if starts_with_ignore_case(value, needle) {
+                    Some(PREFIX)
+                } else if contains_ignore_case(value, needle) {
+                    Some(CONTAINS)
+                } else {
+                    None
+                }
+            },
+            Matcher::Regex(ref regex) => if regex.is_match(value) { Some(CONTAINS) } else { None }
+        }
+    }
+}
+
+struct Term {
+    field: Option<Field>,
+    matcher: Matcher
+}
+
+pub struct Query {
+    terms: Vec<Term>,
+    include_secrets: bool
+}
+
+impl Query {
+    pub fn parse(terms: &[&str], regex: bool, include_secrets: bool) -> Result<Query, SearchError> {
+        if terms.is_empty() {
+            return Err(SearchError::EmptyQuery);
+        }
+        let mut parsed = Vec::new();
+        for term in terms {
+            let (field, value) = match term.find(':') {
+                Some(index) => match Field::parse(&term[..index]) {
+                    Some(field) => (Some(field), &term[index + 1..]),
+                    // Only known field names are prefixes, "http://..." is a value
+                    None if term[..index].chars().all(|c| c.is_alphabetic()) && !term[index + 1..].starts_with("//") =>
+                        return Err(SearchError::UnknownField(term[..index].to_string())),
+                    None => (None, *term)
+                },
+                None => (None, *term)
+            };
+            if field == Some(Field::Secret) && !include_secrets {
+                return Err(SearchError::SecretsNotIncluded);
+            }
+            let matcher = if regex {
+                match Regex::new(&format!("(?i){}", value)) {
+                    Ok(regex) => Matcher::Regex(regex),
+                    Err(why) => return Err(SearchError::InvalidRegex(format!("{}", why)))
+                }
+            } else {
+                Matcher::Substring(lowercase(value).collect())
+            };
+            parsed.push(Term { field, matcher });
+        }
+        Ok(Query { terms: parsed, include_secrets })
+    }
+
+    /// Relevance of the entry, None if some term doesn't match.
+    pub fn score(&self, entry: &mut Entry) -> Option<u32> {
+        let mut total = 0;
+        for term in self.terms.iter() {
+            let fields: Vec<Field> = match term.field {
+                Some(field) => vec![field],
+                None => ALL_FIELDS.iter().cloned()
+                    .filter(|field| *field != Field::Secret || self.include_secrets)
+                    .collect()
+            };
+            let best = fields.iter()
+                .filter_map(|field| field_quality(entry, *field, &term.matcher).map(|q| q * field.weight()))
+                .max();
+            match best {
+                Some(score) => total += score,
+                None => return None
+            }
+        }
+        Some(total)
+    }
+}
+
+/// Indices of the matching entries, the most relevant first.
+pub fn search(query: &Query, entries: &mut [Entry]) -> Vec<usize> {
+    let mut scored: Vec<(u32, usize)> = entries.iter_mut()
+        .enumerate()
+        .filter_map(|(index, entry)| query.score(entry).map(|score| (score, index)))
+        .collect();
+    // Stable sort keeps the order of the vault among equally relevant entries
+    scored.sort_by_key(|&(score, _)| cmp::Reverse(score));
+    scored.into_iter().map(|(_, index)| index).collect()
+}
+
+fn field_quality(entry: &mut Entry, field: Field, matcher: &Matcher) -> Option<u32> {
+    match field {
+        Field::Title => matcher.quality(&entry.title),
+        Field::Username => matcher.quality(entry.username()),
+        Field::Url => matcher.quality(&entry.url),
+        Field::Notes => matcher.quality(&entry.notes),
+        Field::Group => matcher.quality(&entry.group),
+        Field::Tag => entry.tags.iter().filter_map(|tag| matcher.quality(tag)).max(),
+        Field::Secret => {
+            let password = entry.inspect_password(|password| matcher.quality(password));
+            let fields = entry.fields.iter_mut()
+                .filter(|custom| custom.is_secret())
+                .filter_map(|custom| custom.inspect_value(|value| matcher.quality(value)))
+                .max();
+            if password > fields { password } else { fields }
+        }
+    }
+}
+
+/// Fuzzy match: the characters of the pattern appear in the text in the same
+/// order, ignoring case. Returns a score, higher for matches whose
+/// characters are closer together and nearer to the start of the text.
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<u32> {
+    let pattern: Vec<char> = lowercase(pattern).collect();
+    if pattern.is_empty() {
+        return None;
+    }
+    let mut matched = 0;
+    let mut first = None;
+    let mut last = 0;
+    'text: for (position, c) in text.chars().enumerate() {
+        // A few characters lowercase to more than one
+        for lower in c.to_lowercase() {
+            if lower == pattern[matched] {
+                if first.is_none() {
+                    first = Some(position);
+                }
+                last = position;
+                matched += 1;
+                if matched == pattern.len() {
+                    break 'text;
+                }
+            }
+        }
+    }
+    if matched < pattern.len() {
+        return None;
+    }
+    let first = first.unwrap_or(0);
+    let gaps = last + 1 - first - pattern.len();
+    Some(1000u32.saturating_sub(10 * gaps as u32).saturating_sub(first as u32))
+}
+
+// Lowercased one character at a time, so that no copy of the value is made (it may
+// be a secret). Unlike str::to_lowercase there is no special final sigma, which
+// keeps the needle and the values comparable.
+fn lowercase(value: &str) -> impl Iterator<Item = char> + '_ {
+    value.chars().flat_map(char::to_lowercase)
+}
+
+// Needle is already lowercase
+fn starts_with_ignore_case(value: &str, needle: &str) -> bool {
+    let mut value = lowercase(value);
+    needle.chars().all(|c| value.next() == Some(c))
+}
+
+fn contains_ignore_case(value: &str, needle: &str) -> bool {
+    needle.is_empty() || value.char_indices().any(|(index, _)| starts_with_ignore_case(&value[index..], needle))
+}
+
+#[cfg(test)]
+mod tests {
+    use db::Entry;
+    use super::{search, fuzzy_score, Query, SearchError};
+
+    fn entries() -> Vec<Entry> {
+        let mut github = Entry::new("GitHub", "alice", "hunter2");
+        github.tags.insert("prod".to_string());
+        let mut mail = Entry::new("Mail", "bob", "secret");
+        mail.notes = "github recovery codes".to_string();
+        vec![mail, github, Entry::new("AWS", "alice", "pass")]
+    }
+
+    #[test]
+    fn test_search_ranking() {
+        let mut entries = entries();
+        // The title match ranks above the match in notes
+        let query = Query::parse(&["github"], false, false).unwrap();
+        assert_eq!(search(&query, &mut entries), vec![1, 0]);
+
+        let query = Query::parse(&["user:alice", "tag:prod"], false, false).unwrap();
+        assert_eq!(search(&query, &mut entries), vec![1]);
+
+        let query = Query::parse(&["^a.s$"], true, false).unwrap();
+        assert_eq!(search(&query, &mut entries), vec![2]);
+    }
+
+    #[test]
+    fn test_search_secrets() {
+        let mut entries = entries();
+        let query = Query::parse(&["hunter"], false, false).unwrap();
+        assert!(search(&query, &mut entries).is_empty());
+        assert!(Query::parse(&["password:hunter"], false, false).err() == Some(SearchError::SecretsNotIncluded));
+
+        let query = Query::parse(&["hunter"], false, true).unwrap();
+        assert_eq!(search(&query, &mut entries), vec![1]);
+        assert!(Query::parse(&["colour:red"], false, false).err() == Some(SearchError::UnknownField("colour".to_string())));
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("gthb", "GitHub").is_some());
+        assert!(fuzzy_score("hbg", "GitHub").is_none());
+        // Compact matches rank higher
+        assert!(fuzzy_score("git", "GitHub") > fuzzy_score("git", "Gmail intranet"));
+        assert!(fuzzy_score("ärz", "ÄRZTEKAMMER").is_some());
+    }
+
+    #[test]
+    fn test_search_unicode_case() {
+        let mut entries = vec![Entry::new("Ärztekammer", "jürgen", "pass"), Entry::new("ΣΟΦΊΑ", "sofia", "pass")];
+        // Folded the same way as with --regex
+        for &regex in &[false, true] {
+            let query = Query::parse(&["ärzte"], regex, false).unwrap();
+            assert_eq!(search(&query, &mut entries), vec![0]);
+            let query = Query::parse(&["user:JÜRGEN"], regex, false).unwrap();
+            assert_eq!(search(&query, &mut entries), vec![0]);
+            let query = Query::parse(&["σοφία"], regex, false).unwrap();
+            assert_eq!(search(&query, &mut entries), vec![1]);
+        }
+        // Title equal ignoring case ranks as exact
+        let query = Query::parse(&["ΣΟΦΊΑ"], false, false).unwrap();
+        assert_eq!(query.score(&mut entries[1]), Some(4 * 8));
+    }
+}
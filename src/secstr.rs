@@ -7,6 +7,7 @@ use rand::{ Rng, OsRng };
 use nacl::stream::{self, stream_encrypt_xor};
 use serde::ser::{Serialize, Serializer};
 use serde::de::{Deserialize, Deserializer, Visitor, Error};
+use crypto::util::fixed_time_eq;
 
 #[doc = "
 SecStr implements a secure string. This means in particular:
@@ -47,7 +48,7 @@ impl SecStr {
         let mut rng = OsRng::new().unwrap();
 
         let mut sec_str = SecStr {
-            string: string,
+            string,
             encrypted_string: vec![],
             password: (0..stream::KEY_BYTES).map(|_| rng.gen::<u8>()).collect(),
             iv: (0..stream::NONCE_BYTES).map(|_| rng.gen::<u8>()).collect()
@@ -104,6 +105,20 @@ impl SecStr {
     }
 }
 
+// Compares the plaintexts, which are wiped right after the comparison
+impl PartialEq for SecStr {
+    fn eq(&self, other: &SecStr) -> bool {
+        let mine = self.export();
+        let theirs = other.export();
+        let equal = mine.len() == theirs.len() && fixed_time_eq(mine.as_bytes(), theirs.as_bytes());
+        unsafe {
+            ptr::write_bytes(mine.as_ptr() as *mut c_void, 0u8, mine.len());
+            ptr::write_bytes(theirs.as_ptr() as *mut c_void, 0u8, theirs.len());
+        }
+        equal
+    }
+}
+
 // Make sure sensitive information is not logged accidentally
 impl fmt::Debug for SecStr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -120,6 +135,13 @@ impl fmt::Display for SecStr {
 // string value and encrypted_string value will be overwritten with zeroes after drop of struct
 impl Drop for SecStr {
     fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+impl SecStr {
+    // Separate from drop() so that the test can look at the memory before it is freed
+    fn wipe(&mut self) {
         self.delete();
         unsafe { mlock(self.string.as_ptr() as *const c_void,
                                self.string.len() as size_t);
@@ -134,26 +156,34 @@ impl Drop for SecStr {
 
 // Serialization infrastructure
 impl Serialize for SecStr {
-    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where S: Serializer,
     {
-        serializer.visit_str(self.export().as_ref())
+        // The plaintext copy is wiped as soon as the serializer has it
+        let plain = self.export();
+        let res = serializer.serialize_str(plain.as_ref());
+        unsafe { ptr::write_bytes(plain.as_ptr() as *mut c_void, 0u8, plain.len()); }
+        res
     }
 }
 
-impl Deserialize for SecStr {
-    fn deserialize<D>(deserializer: &mut D) -> Result<SecStr, D::Error>
-        where D: Deserializer,
+impl<'de> Deserialize<'de> for SecStr {
+    fn deserialize<D>(deserializer: D) -> Result<SecStr, D::Error>
+        where D: Deserializer<'de>,
     {
-        deserializer.visit(SecStrVisitor)
+        deserializer.deserialize_str(SecStrVisitor)
     }
 }
 
 struct SecStrVisitor;
-impl Visitor for SecStrVisitor {
+impl<'de> Visitor<'de> for SecStrVisitor {
     type Value = SecStr; // associated type
 
-    fn visit_str<E>(&mut self, v: &str) -> Result<SecStr, E>
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<SecStr, E>
         where E: Error,
     {
         Ok(SecStr::new(v.to_string()))
@@ -164,25 +194,16 @@ impl Visitor for SecStrVisitor {
 mod tests {
     use super::SecStr;
     use std::str;
-    use std::ptr::copy;
 
     #[test]
     fn test_drop() {
-        let mut test_vec:  Vec<u8> = Vec::with_capacity(4);
-        let mut test_vec2: Vec<u8> = Vec::with_capacity(4);
-        unsafe {
-            test_vec.set_len(4);
-            test_vec2.set_len(4);
-            let str = "drop".to_string();
-            let mut sec_str = SecStr::new(str);
-            let enc_str_ptr = sec_str.encrypted_string.as_mut_ptr();
-            let str_ptr = sec_str.string.as_mut_vec().as_mut_ptr();
-            drop(sec_str);
-            copy(enc_str_ptr, test_vec.as_mut_ptr(), 4);
-            copy(str_ptr, test_vec2.as_mut_ptr(), 4);
-        }
-        assert_eq!(test_vec,  vec![0u8, 0u8, 0u8, 0u8]);
-        assert_eq!(test_vec2, vec![0u8, 0u8, 0u8, 0u8]);
+        // Freed memory is reused by the allocator, so check what drop() does before the free
+        let str = "drop".to_string();
+        let mut sec_str = SecStr::new(str);
+        sec_str.unlock();
+        sec_str.wipe();
+        assert_eq!(sec_str.encrypted_string, vec![0u8, 0u8, 0u8, 0u8]);
+        assert_eq!(sec_str.string.as_bytes(), &[0u8, 0u8, 0u8, 0u8]);
     }
     #[test]
     fn test_new() {
@@ -216,6 +237,12 @@ mod tests {
         assert_eq!(sec_str.string, "delete");
     }
 
+    #[test]
+    fn test_eq() {
+        assert!(SecStr::new("same".to_string()) == SecStr::new("same".to_string()));
+        assert!(SecStr::new("same".to_string()) != SecStr::new("other".to_string()));
+    }
+
     #[test]
     fn test_encryption() {
         let str = "delete".to_string();
@@ -0,0 +1,163 @@
+//!
+//! Splitting of interactive command lines into words, with shell-like quoting.
+//!
+//! Words are separated by whitespace. Inside single quotes everything is taken
+//! literally, inside double quotes a backslash escapes `"` and `\`, and outside
+//! of quotes a backslash escapes any character. So `show "My Bank"`,
+//! `show 'My Bank'` and `show My\ Bank` all address the entry `My Bank`.
+//!
+
+use std::error;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum SplitError {
+    UnterminatedQuote(char),
+    TrailingBackslash
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SplitError::UnterminatedQuote(quote) => write!(f, "missing closing {}", quote),
+            SplitError::TrailingBackslash => write!(f, "nothing to escape after the last backslash")
+        }
+    }
+}
+
+impl error::Error for SplitError {
+    fn description(&self) -> &str {
+        match *self {
+            SplitError::UnterminatedQuote(_) => "unterminated quote",
+            SplitError::TrailingBackslash => "trailing backslash"
+        }
+    }
+}
+
+/// The line split into words, with the quotes and escapes removed.
+pub fn split(line: &str) -> Result<Vec<String>, SplitError> {
+    let state = scan(line);
+    if let Some(quote) = state.quote {
+        return Err(SplitError::UnterminatedQuote(quote));
+    }
+    if state.escaped {
+        return Err(SplitError::TrailingBackslash);
+    }
+    let mut words = state.words;
+    words.extend(state.current);
+    Ok(words)
+}
+
+/// The last, possibly unfinished word of the line: the byte offset where it
+/// starts and its value without quotes. Used for completion, so an unterminated
+/// quote is fine.
+pub fn last_word(line: &str) -> (usize, String) {
+    let state = scan(line);
+    match state.current {
+        Some(word) => (state.current_start, word),
+        None => (line.len(), String::new())
+    }
+}
+
+/// The word quoted so that `split` gives it back. With `closed` false the
+/// closing quote is left out, for a word that is still being completed.
+pub fn quote(word: &str, closed: bool) -> String {
+    let plain = !word.is_empty() && !word.chars().any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\');
+    if plain {
+        return word.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in word.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    if closed {
+        quoted.push('"');
+    }
+    quoted
+}
+
+struct ScanState {
+    words: Vec<String>,
+    /// The word being read, None between words.
+    current: Option<String>,
+    current_start: usize,
+    quote: Option<char>,
+    escaped: bool
+}
+
+fn scan(line: &str) -> ScanState {
+    let mut state = ScanState { words: Vec::new(), current: None, current_start: 0, quote: None, escaped: false };
+    for (index, c) in line.char_indices() {
+        if state.current.is_none() && !c.is_whitespace() {
+            state.current = Some(String::new());
+            state.current_start = index;
+        }
+        if state.escaped {
+            state.escaped = false;
+            // Inside double quotes only the quote and the backslash itself are escaped
+            if state.quote == Some('"') && c != '"' && c != '\\' {
+                push(&mut state, '\\');
+            }
+            push(&mut state, c);
+            continue;
+        }
+        match (state.quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => state.quote = None,
+            (Some('\''), c) => push(&mut state, c),
+            (_, '\\') => state.escaped = true,
+            (Some(_), c) => push(&mut state, c),
+            (None, '\'') | (None, '"') => state.quote = Some(c),
+            (None, c) if c.is_whitespace() => {
+                if let Some(word) = state.current.take() {
+                    state.words.push(word);
+                }
+            },
+            (None, c) => push(&mut state, c)
+        }
+    }
+    state
+}
+
+fn push(state: &mut ScanState, c: char) {
+    if let Some(ref mut word) = state.current {
+        word.push(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split, last_word, quote, SplitError};
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("  show   github ").unwrap(), vec!["show", "github"]);
+        assert_eq!(split("show \"My Bank\"").unwrap(), vec!["show", "My Bank"]);
+        assert_eq!(split("show 'a \\ b' My\\ Bank").unwrap(), vec!["show", "a \\ b", "My Bank"]);
+        assert_eq!(split("new \"say \\\"hi\\\" \\n\"").unwrap(), vec!["new", "say \"hi\" \\n"]);
+        assert_eq!(split("show \"\"").unwrap(), vec!["show", ""]);
+        assert!(split("   ").unwrap().is_empty());
+
+        assert_eq!(split("show \"My Bank"), Err(SplitError::UnterminatedQuote('"')));
+        assert_eq!(split("show My\\"), Err(SplitError::TrailingBackslash));
+    }
+
+    #[test]
+    fn test_last_word() {
+        assert_eq!(last_word("show \"My B"), (5, "My B".to_string()));
+        assert_eq!(last_word("show My\\ B"), (5, "My B".to_string()));
+        assert_eq!(last_word("show "), (5, String::new()));
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("github", true), "github");
+        assert_eq!(quote("My Bank", true), "\"My Bank\"");
+        assert_eq!(quote("My B", false), "\"My B");
+        for word in &["My \"Bank\"", "back\\slash", "it's", ""] {
+            assert_eq!(split(&quote(word, true)).unwrap(), vec![word.to_string()]);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-pub static USAGE: &'static str = "Usage: rpass [OPTIONS] COMMAND [arg...]
+pub static USAGE: &str = "Usage: rpass [OPTIONS] COMMAND [arg...]
 
 RustyPass is keeping your passwords safe, while:
     * being memory safe by default (unlike C/C++)
@@ -6,18 +6,77 @@ RustyPass is keeping your passwords safe, while:
     * no interpreted code (unlike python, ruby)
 
 Commands:
-    new \t Create new database
-    open \t Open existing database
+    create <file> [kdf options] \t Create new database
+    kdf-benchmark \t Suggest key derivation parameters taking about 1 second on this machine
+    open <file> \t Open existing database and enter interactive mode
+    agent <file>... [--idle <minutes>] [--lifetime <minutes>] [--foreground]
+          \t Keep the databases unlocked in the background for list, show and copy
+    agent lock \t Wipe the databases from the agent's memory and stop it
+
+One-shot commands (for scripting):
+    list <file> [<group>] [--tree] [--format json|table|tsv]
+    show <file> <entry> [--field <name>] [--format json|table|tsv] [--reveal]
+    copy <file> <entry> [seconds]
+    new <file> [--generate [generator options]]
+    edit <file> <entry> [--generate [generator options]]
+    generate [--length <n>] [--min-each <n>] [--no-lower] [--no-upper] [--no-digits]
+             [--no-symbols] [--exclude-ambiguous]
+    passphrase [--words <n>] [--separator <s>] [--capitalize] [--digit] [--wordlist <file>]
+               (at most 64 words, from the BIP-39 English list unless --wordlist is given)
+    remove <file> <entry> [--yes]
+    passwd <file> [kdf options]
+    backups <file>
+    restore <file> <backup number>
+    settings <file> [<name> <value>]
+    stale <file> [--older-than <age>] [--format json|table|tsv]
+    history <file> <entry> [--format json|table|tsv] [--reveal]
+    restore-password <file> <entry> <n>
+    trash <file> list [--format json|table|tsv] | restore <entry> | empty
+    mkdir <file> <group>
+    mv <file> <entry> <group> | mv <file> <group>/ <group>
+    rmdir <file> <group>
+    search <file> <term>... [--regex] [--include-secrets] [--format json|table|tsv]
+
+Key derivation options of create:
+    --kdf argon2id|scrypt|bcrypt (default argon2id)
+    --memory <MiB> --iterations <n> --parallelism <n> (argon2id, default 64 MiB, 3, 1)
+    --log-n <n> (scrypt, default 15)
+    --cost <n> (bcrypt, default 10)
 
 Options:
-    -h, --help\t Show this help";
+    -h, --help\t Show this help
+    --password-fd <fd>\t Read master password from the file descriptor
+    --password-env <var>\t Read master password from the environment variable
+
+Exit codes:
+    0 success, 1 I/O error, 2 invalid arguments, 3 wrong master password,
+    4 entry not found, 5 database file not found, 6 corrupted database,
+    7 invalid input, 8 clipboard error, 9 database locked by another process,
+    10 database changed by another process and cannot be merged";
 
-pub static DB_COMMANDS: &'static str = "Available commands:
-    list \t List all entries
-    new \t Add new entry
-    show <entry> \t Show entry details
-    copy <entry>\t Copy entry password to clipboard
-    edit <entry> \t Edit entry details
-    remove <entry>\t Remove entry";
+pub static DB_COMMANDS: &str = "Available commands:
+    list [<group>] [--tree] [--format <f>] \t List entries (of the group), format is one of table (default), json, tsv
+    new [--generate [options]] \t Add new entry, empty password generates a random one
+    show <entry> [--format <f>] [--reveal] \t Show entry details, password is shown only with --reveal
+    copy <entry> [seconds]\t Copy entry password to clipboard, cleared after 30 seconds by default (or on lock and exit)
+    edit <entry> [--generate [options]] \t Edit entry details (Enter keeps the current value)
+    remove <entry>\t Move entry to the trash
+    passwd [kdf options] \t Change the master password (and optionally the key derivation parameters)
+    backups \t List backups of the database file
+    restore <n> \t Replace the database with backup number n
+    settings [<name> <value>] \t Show or change settings of the database
+    stale [--older-than <age>] \t List entries with passwords older than the age (default 180d)
+    history <entry> [--reveal] \t List previous passwords of the entry
+    restore-password <entry> <n> \t Make previous password number n current again
+    trash list|restore <entry>|empty \t List, restore or delete removed entries
+    mkdir <group> \t Create a group, e.g. work/aws/prod
+    mv <entry> <group> \t Move an entry to a group (mv <group>/ <group> moves a whole group)
+    rmdir <group> \t Remove an empty group
+    search <term>... [--regex] \t Find entries, terms can be scoped like user:alice tag:prod
+    generate [options] \t Generate a random password, see rpass --help for options
+    passphrase [options] \t Generate a memorable passphrase, see rpass --help for options
+    lock \t Wipe the opened database from memory until the master password is entered again
+    exit, quit \t Close the database (so does Ctrl-D)
 
-//Tip: You don't have to type entry's full name, type only prefix and press TAB to autocomplete.";
+Tip: You don't have to type entry's full name, type a part of it and press TAB to autocomplete.
+Quote titles with spaces like in a shell: show \"My Bank\" or show My\\ Bank";
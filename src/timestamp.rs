@@ -0,0 +1,81 @@
+//!
+//! Unix timestamps (seconds since the epoch) and their human readable form.
+//!
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub type Timestamp = u64;
+
+pub fn now() -> Timestamp {
+    from_system_time(SystemTime::now())
+}
+
+pub fn from_system_time(time: SystemTime) -> Timestamp {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_) => 0
+    }
+}
+
+/// Format as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format(timestamp: Timestamp) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year, month, day, seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+/// Parse a length of time like `180d`, units are `h`, `d`, `w`, `m` (30 days) and `y` (365 days).
+/// Returns the number of seconds.
+pub fn parse_duration(input: &str) -> Option<u64> {
+    if input.len() < 2 {
+        return None;
+    }
+    let (number, unit) = input.split_at(input.len() - 1);
+    let multiplier = match unit {
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        "m" => 30 * 86400,
+        "y" => 365 * 86400,
+        _ => return None
+    };
+    number.parse::<u64>().ok().and_then(|number| number.checked_mul(multiplier))
+}
+
+// Days since 1970-01-01 to a (year, month, day) date, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, parse_duration};
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format(0), "1970-01-01 00:00:00");
+        assert_eq!(format(951782400), "2000-02-29 00:00:00");
+        assert_eq!(format(1700000000), "2023-11-14 22:13:20");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("180d"), Some(180 * 86400));
+        assert_eq!(parse_duration("2w"), Some(14 * 86400));
+        assert_eq!(parse_duration("d"), None);
+        assert_eq!(parse_duration("180"), None);
+        assert_eq!(parse_duration("-1d"), None);
+    }
+}